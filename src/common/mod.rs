@@ -2,3 +2,4 @@ pub mod arithmetics;
 pub mod formatting;
 pub mod parsing;
 pub mod proto;
+pub mod timers;
@@ -0,0 +1,66 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::time::Instant;
+
+// A heap of deadlines, each identified by a key, ordered by which expires first.
+// Timers are cancelled lazily, i.e. whoever owns a key decides whether an expired deadline is still relevant.
+pub struct Timers<K> {
+    deadlines: BinaryHeap<Reverse<Deadline<K>>>,
+}
+
+struct Deadline<K> {
+    expiry: Instant,
+    key: K,
+}
+
+impl<K> PartialEq for Deadline<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.expiry == other.expiry
+    }
+}
+
+impl<K> Eq for Deadline<K> {}
+
+impl<K> PartialOrd for Deadline<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K> Ord for Deadline<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.expiry.cmp(&other.expiry)
+    }
+}
+
+impl<K> Default for Timers<K> {
+    fn default() -> Self {
+        Timers::new()
+    }
+}
+
+impl<K> Timers<K> {
+    pub fn new() -> Self {
+        Timers {
+            deadlines: BinaryHeap::new(),
+        }
+    }
+
+    pub fn register(&mut self, expiry: Instant, key: K) {
+        self.deadlines.push(Reverse(Deadline { expiry, key }));
+    }
+
+    // The earliest deadline of all registered timers.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.deadlines.peek().map(|deadline| deadline.0.expiry)
+    }
+
+    // Removes and returns the key of a timer that has expired by now, if any.
+    pub fn pop_expired(&mut self, now: Instant) -> Option<K> {
+        if self.next_expiry()? > now {
+            return None;
+        }
+
+        self.deadlines.pop().map(|deadline| deadline.0.key)
+    }
+}
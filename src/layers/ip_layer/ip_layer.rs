@@ -1,8 +1,12 @@
 use crate::common::proto::Proto;
 use crate::layers::ip_layer::ipv4::ipv4::IPv4;
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::transport_layer::TransportLayer;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
+use eyre::{Context, ContextCompat};
+
 use super::ipv6::ipv6::IPv6;
 
 #[derive(Clone, Debug)]
@@ -23,22 +27,37 @@ impl Display for IPLayerProtocol {
 }
 
 impl IPLayerProtocol {
-    pub fn parse(bytes: &mut &[u8]) -> IPLayerProtocol {
-        let first_byte = bytes[0];
+    pub fn parse(bytes: &mut &[u8]) -> eyre::Result<IPLayerProtocol> {
+        let first_byte = *bytes.first().wrap_err("empty ip packet")?;
         let version = (first_byte & 0xf0) >> 4;
 
-        match version {
-            4 => IPLayerProtocol::IPv4(IPv4::parse(bytes).expect("parsing ipv4")),
-            6 => IPLayerProtocol::IPv6(IPv6::parse(bytes).expect("parsing ipv6")),
+        Ok(match version {
+            4 => IPLayerProtocol::IPv4(IPv4::parse(bytes).wrap_err("parsing ipv4")?),
+            6 => IPLayerProtocol::IPv6(IPv6::parse(bytes).wrap_err("parsing ipv6")?),
             _ => IPLayerProtocol::Other(bytes.to_vec()),
-        }
+        })
     }
 
     pub fn serialize(&self) -> eyre::Result<Vec<u8>> {
         match self {
             IPLayerProtocol::IPv4(ipv4) => ipv4.serialize(),
             IPLayerProtocol::Other(data) => Ok(data.to_vec()),
-            IPLayerProtocol::IPv6(_ipv6) => todo!(),
+            IPLayerProtocol::IPv6(_ipv6) => eyre::bail!("serializing ipv6 is not yet supported"),
+        }
+    }
+
+    // Generates a new IP packet carrying the provided data from source_address to destination_address.
+    pub fn generate(
+        source_address: IPAddress,
+        destination_address: IPAddress,
+        data: TransportLayer,
+    ) -> eyre::Result<IPLayerProtocol> {
+        match (source_address, destination_address) {
+            (IPAddress::V4(src), IPAddress::V4(dst)) => Ok(IPv4::generate(src, dst, data)?.into()),
+            (IPAddress::V6(_), IPAddress::V6(_)) => {
+                eyre::bail!("generating IPv6 packets is not yet supported")
+            }
+            (src, dst) => eyre::bail!("mismatched address families {src:?} → {dst:?}"),
         }
     }
 }
@@ -54,3 +73,15 @@ impl Into<IPLayerProtocol> for IPv6 {
         IPLayerProtocol::IPv6(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_packets_are_errors() {
+        assert!(IPLayerProtocol::parse(&mut &[][..]).is_err());
+        // An IPv4 header cut off after its first bytes.
+        assert!(IPLayerProtocol::parse(&mut &[0x45, 0x00, 0x00][..]).is_err());
+    }
+}
@@ -190,6 +190,19 @@ impl Proto for IPv4 {
 
 impl IPv4 {
     pub fn generate_response(&self, data: TransportLayer) -> eyre::Result<Self> {
+        Self::generate(
+            self.destination_address.clone(),
+            self.source_address.clone(),
+            data,
+        )
+    }
+
+    // Generates a new packet from scratch, i.e. one that is not a response to anything we have received.
+    pub fn generate(
+        source_address: IPAddressV4,
+        destination_address: IPAddressV4,
+        data: TransportLayer,
+    ) -> eyre::Result<Self> {
         let internet_header_length: U4 = 5; // TODO: Account for options and padding
         let total_length: u16 = (4 as u16)
             .checked_mul(internet_header_length as u16) // Header length
@@ -197,6 +210,14 @@ impl IPv4 {
             .checked_add(data.len()?) // Add the data length
             .wrap_err("data too large for ipv4")?;
 
+        let protocol = match &data {
+            TransportLayer::TCP(_) => Protocol::TCP,
+            TransportLayer::UDP(_) => Protocol::UDP,
+            TransportLayer::ICMPv6(_) | TransportLayer::Other(_) => {
+                eyre::bail!("generating ipv4 packets only supports TCP & UDP")
+            }
+        };
+
         Ok(IPv4 {
             version: 4,
             internet_header_length: 5, // TODO: Account for options and padding
@@ -206,10 +227,10 @@ impl IPv4 {
             flags: Flags::default(),
             fragment_offset: 0,
             time_to_live: 0b00111100, // As set out in the TCP RFC.
-            protocol: Protocol::TCP,
+            protocol,
             header_checksum: 0, // TODO: Calculate
-            source_address: self.destination_address.clone(),
-            destination_address: self.source_address.clone(),
+            source_address,
+            destination_address,
             options_and_padding: vec![], // TODO: lol
             data,
         })
@@ -99,7 +99,7 @@ impl Proto for IPv6 {
 }
 
 impl IPv6 {
-    pub fn generate_response(&self, data: TransportLayer) -> eyre::Result<Self> {
-        todo!("not yet implemented")
+    pub fn generate_response(&self, _data: TransportLayer) -> eyre::Result<Self> {
+        eyre::bail!("generating ipv6 responses is not yet supported")
     }
 }
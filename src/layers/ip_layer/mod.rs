@@ -1,3 +1,5 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
 use ipv4::ipv4_address::IPAddressV4;
 use ipv6::ipv6_address::IPAddressV6;
 
@@ -32,3 +34,12 @@ impl Into<IPAddress> for IPAddressV6 {
         IPAddress::V6(self)
     }
 }
+
+impl From<IPAddress> for IpAddr {
+    fn from(address: IPAddress) -> Self {
+        match address {
+            IPAddress::V4(address) => IpAddr::V4(Ipv4Addr::from(address.0)),
+            IPAddress::V6(address) => IpAddr::V6(Ipv6Addr::from(address.0)),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::time::{Duration, Instant};
+
+use crate::layers::transport_layer::tcp::congestion_control::{
+    initial_window, CongestionControl, CongestionControlAlgorithm,
+};
+
+// The constants of the window growth function, as specified in https://datatracker.ietf.org/doc/html/rfc9438#section-4.1
+const C: f64 = 0.4; // C, in segments per second cubed.
+const BETA: f64 = 0.7; // beta_cubic, the multiplicative window decrease factor.
+
+// CUBIC, which grows the window as a cubic function of the time since the last congestion event,
+// as specified in https://datatracker.ietf.org/doc/html/rfc9438
+// The windows are kept in segments, so they can grow by fractions of a segment.
+#[derive(Clone, Debug)]
+pub struct Cubic {
+    maximum_segment_size: u32, // SMSS
+    congestion_window: f64,    // cwnd
+    slow_start_threshold: u32, // ssthresh, in bytes.
+    // The window just before the last window reduction (W_max).
+    window_max: f64,
+    // When the current congestion avoidance stage started (t_epoch), None outside of congestion avoidance.
+    epoch_start: Option<Instant>,
+    // The time it takes the window to grow back to W_max (K), in seconds.
+    k: f64,
+    // The window a Reno sender would have by now (W_est), to never do worse than Reno.
+    window_estimate: f64,
+}
+
+impl Cubic {
+    pub fn new(maximum_segment_size: u16) -> Self {
+        let maximum_segment_size = maximum_segment_size as u32;
+        let congestion_window =
+            initial_window(maximum_segment_size) as f64 / maximum_segment_size as f64;
+
+        Cubic {
+            maximum_segment_size,
+            congestion_window,
+            // As high as possible, so the first loss ends slow start.
+            slow_start_threshold: u32::MAX,
+            window_max: congestion_window,
+            epoch_start: None,
+            k: 0.0,
+            window_estimate: congestion_window,
+        }
+    }
+
+    // W_cubic(t), the window t seconds into the congestion avoidance stage.
+    // As specified in https://datatracker.ietf.org/doc/html/rfc9438#section-4.2
+    fn cubic_window(&self, t: f64) -> f64 {
+        C * (t - self.k).powi(3) + self.window_max
+    }
+
+    // Starts a congestion avoidance stage, which grows the window back to W_max in K seconds.
+    fn start_epoch(&mut self, now: Instant) {
+        self.epoch_start = Some(now);
+        // Without a congestion event before the window is already past W_max, so it grows from where it is.
+        self.window_max = self.window_max.max(self.congestion_window);
+        self.k = ((self.window_max - self.congestion_window) / C).cbrt();
+        self.window_estimate = self.congestion_window;
+    }
+
+    // Reduces the window after a congestion event, remembering where it was as W_max.
+    // As specified in https://datatracker.ietf.org/doc/html/rfc9438#section-4.6
+    fn reduce(&mut self, flight_size: u32) {
+        // Fast convergence, release bandwidth to new flows if the window did not get back to W_max.
+        self.window_max = if self.congestion_window < self.window_max {
+            self.congestion_window * (1.0 + BETA) / 2.0
+        } else {
+            self.congestion_window
+        };
+
+        self.slow_start_threshold =
+            ((flight_size as f64 * BETA) as u32).max(2 * self.maximum_segment_size);
+        self.epoch_start = None;
+    }
+
+    fn segments(&self, bytes: u32) -> f64 {
+        bytes as f64 / self.maximum_segment_size as f64
+    }
+}
+
+impl CongestionControl for Cubic {
+    fn algorithm(&self) -> CongestionControlAlgorithm {
+        CongestionControlAlgorithm::Cubic
+    }
+
+    fn congestion_window(&self) -> u32 {
+        (self.congestion_window * self.maximum_segment_size as f64).min(u32::MAX as f64) as u32
+    }
+
+    fn slow_start_threshold(&self) -> u32 {
+        self.slow_start_threshold
+    }
+
+    fn on_acknowledgement(
+        &mut self,
+        acknowledged: u32,
+        now: Instant,
+        round_trip_time: Option<Duration>,
+    ) {
+        // Slow start grows the window by at most one segment per acknowledgement.
+        if self.congestion_window() < self.slow_start_threshold {
+            self.congestion_window += self.segments(acknowledged.min(self.maximum_segment_size));
+            return;
+        }
+
+        if self.epoch_start.is_none() {
+            self.start_epoch(now);
+        }
+        let t = self
+            .epoch_start
+            .map_or(0.0, |start| now.duration_since(start).as_secs_f64());
+        let round_trip_time = round_trip_time.unwrap_or_default().as_secs_f64();
+        let acknowledged = self.segments(acknowledged);
+
+        // The Reno-friendly region, grow like Reno would, more aggressively once past W_max.
+        // As specified in https://datatracker.ietf.org/doc/html/rfc9438#section-4.3
+        let alpha = if self.window_estimate >= self.window_max {
+            1.0
+        } else {
+            3.0 * (1.0 - BETA) / (1.0 + BETA)
+        };
+        self.window_estimate += alpha * acknowledged / self.congestion_window;
+
+        if self.cubic_window(t) < self.window_estimate {
+            self.congestion_window = self.window_estimate;
+            return;
+        }
+
+        // The concave & convex regions, approach the window CUBIC aims for one round trip from now.
+        // As specified in https://datatracker.ietf.org/doc/html/rfc9438#section-4.4
+        let target = self
+            .cubic_window(t + round_trip_time)
+            .clamp(self.congestion_window, 1.5 * self.congestion_window);
+        self.congestion_window +=
+            (target - self.congestion_window) / self.congestion_window * acknowledged;
+    }
+
+    fn on_congestion_event(&mut self, flight_size: u32, _now: Instant) {
+        self.reduce(flight_size);
+        // The three duplicate acknowledgements stand for segments that have left the network.
+        self.congestion_window = self.segments(self.slow_start_threshold) + 3.0;
+    }
+
+    fn on_duplicate_acknowledgement(&mut self) {
+        self.congestion_window += 1.0;
+    }
+
+    fn on_partial_acknowledgement(&mut self, acknowledged: u32) {
+        // Deflate the window by the acknowledged data, making room for the retransmission if it was a full segment.
+        self.congestion_window = (self.congestion_window - self.segments(acknowledged)).max(0.0);
+        if acknowledged >= self.maximum_segment_size {
+            self.congestion_window += 1.0;
+        }
+    }
+
+    fn on_recovery_exit(&mut self, flight_size: u32) {
+        let window = self
+            .slow_start_threshold
+            .min(flight_size.max(self.maximum_segment_size) + self.maximum_segment_size);
+        self.congestion_window = self.segments(window);
+    }
+
+    // As specified in https://datatracker.ietf.org/doc/html/rfc9438#section-4.8
+    fn on_retransmission_timeout(&mut self, flight_size: u32, _now: Instant) {
+        self.reduce(flight_size);
+        // The loss window, which starts slow start all over again.
+        self.congestion_window = 1.0;
+    }
+
+    fn clone_box(&self) -> Box<dyn CongestionControl> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: u32 = 1000;
+
+    // CUBIC in congestion avoidance with the given window, after a loss at W_max.
+    fn cubic(congestion_window: f64, window_max: f64) -> Cubic {
+        Cubic {
+            congestion_window,
+            window_max,
+            slow_start_threshold: (congestion_window * MSS as f64) as u32,
+            ..Cubic::new(MSS as u16)
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn slow_start_grows_by_at_most_one_segment_per_acknowledgement() {
+        let mut cubic = Cubic::new(MSS as u16);
+        assert_eq!(cubic.congestion_window(), 4 * MSS);
+
+        cubic.on_acknowledgement(MSS / 2, Instant::now(), None);
+        assert_eq!(cubic.congestion_window(), 4 * MSS + MSS / 2);
+
+        cubic.on_acknowledgement(3 * MSS, Instant::now(), None);
+        assert_eq!(cubic.congestion_window(), 5 * MSS + MSS / 2);
+    }
+
+    #[test]
+    fn epoch_grows_back_to_window_max_in_k_seconds() {
+        let mut cubic = cubic(7.0, 10.0);
+        let now = Instant::now();
+        cubic.on_acknowledgement(MSS, now, Some(Duration::ZERO));
+
+        // K = cubic_root((W_max - cwnd_epoch) / C)
+        assert_close(cubic.k, (3.0 / C).cbrt());
+        assert_close(cubic.cubic_window(0.0), 7.0);
+        assert_close(cubic.cubic_window(cubic.k), 10.0);
+    }
+
+    #[test]
+    fn reno_friendly_region_follows_w_est() {
+        let mut cubic = cubic(7.0, 10.0);
+        let now = Instant::now();
+        cubic.on_acknowledgement(MSS, now, Some(Duration::ZERO));
+
+        // W_cubic(0) is the window at the start of the epoch, below W_est after the acknowledgement.
+        let alpha = 3.0 * (1.0 - BETA) / (1.0 + BETA);
+        assert_close(cubic.window_estimate, 7.0 + alpha / 7.0);
+        assert_close(cubic.congestion_window, cubic.window_estimate);
+    }
+
+    #[test]
+    fn concave_region_approaches_w_cubic() {
+        let mut cubic = cubic(7.0, 10.0);
+        let now = Instant::now();
+        cubic.on_acknowledgement(MSS, now, Some(Duration::ZERO));
+        let window = cubic.congestion_window;
+
+        let later = now + Duration::from_secs(3);
+        cubic.on_acknowledgement(MSS, later, Some(Duration::ZERO));
+        let target = cubic.cubic_window(3.0);
+
+        assert!(target > cubic.window_estimate);
+        assert_close(cubic.congestion_window, window + (target - window) / window);
+    }
+
+    #[test]
+    fn congestion_event_reduces_by_beta() {
+        let mut cubic = cubic(10.0, 10.0);
+        cubic.on_congestion_event(10 * MSS, Instant::now());
+
+        assert_close(cubic.window_max, 10.0);
+        assert_eq!(cubic.slow_start_threshold(), 7 * MSS);
+        assert_eq!(cubic.congestion_window(), 10 * MSS);
+
+        cubic.on_duplicate_acknowledgement();
+        assert_eq!(cubic.congestion_window(), 11 * MSS);
+        cubic.on_partial_acknowledgement(MSS);
+        assert_eq!(cubic.congestion_window(), 11 * MSS);
+
+        cubic.on_recovery_exit(10 * MSS);
+        assert_eq!(cubic.congestion_window(), 7 * MSS);
+        cubic.on_recovery_exit(2 * MSS);
+        assert_eq!(cubic.congestion_window(), 3 * MSS);
+    }
+
+    #[test]
+    fn fast_convergence_lowers_window_max() {
+        // The window did not get back to W_max before the next loss.
+        let mut cubic = cubic(8.0, 10.0);
+        cubic.on_congestion_event(8 * MSS, Instant::now());
+
+        assert_close(cubic.window_max, 8.0 * (1.0 + BETA) / 2.0);
+    }
+
+    #[test]
+    fn retransmission_timeout_collapses_the_window() {
+        let mut cubic = cubic(10.0, 10.0);
+        cubic.on_retransmission_timeout(10 * MSS, Instant::now());
+
+        assert_eq!(cubic.congestion_window(), MSS);
+        assert_eq!(cubic.slow_start_threshold(), 7 * MSS);
+        assert!(cubic.epoch_start.is_none());
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+use crate::layers::transport_layer::tcp::congestion_control::cubic::Cubic;
+use crate::layers::transport_layer::tcp::congestion_control::new_reno::NewReno;
+
+pub mod cubic;
+pub mod new_reno;
+
+// The congestion control algorithms a connection can use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CongestionControlAlgorithm {
+    NewReno,
+    Cubic,
+}
+
+impl CongestionControlAlgorithm {
+    // Creates the congestion control for a connection sending segments of at most the given size.
+    pub fn create(self, maximum_segment_size: u16) -> Box<dyn CongestionControl> {
+        match self {
+            CongestionControlAlgorithm::NewReno => Box::new(NewReno::new(maximum_segment_size)),
+            CongestionControlAlgorithm::Cubic => Box::new(Cubic::new(maximum_segment_size)),
+        }
+    }
+}
+
+// Decides how much data a connection may have in flight, based on the acknowledgements & losses the TCB detects.
+// The TCB runs the loss recovery itself, i.e. fast retransmit & fast recovery as specified in
+// https://datatracker.ietf.org/doc/html/rfc6582#section-3.2 and tells the congestion control about every step.
+// All sizes are in bytes.
+pub trait CongestionControl: Debug + Send {
+    fn algorithm(&self) -> CongestionControlAlgorithm;
+
+    // The congestion window (cwnd), the amount of data we may have in flight.
+    fn congestion_window(&self) -> u32;
+
+    // The slow start threshold (ssthresh), below which the congestion window grows exponentially.
+    fn slow_start_threshold(&self) -> u32;
+
+    // New data has been acknowledged outside of fast recovery.
+    fn on_acknowledgement(
+        &mut self,
+        acknowledged: u32,
+        now: Instant,
+        round_trip_time: Option<Duration>,
+    );
+
+    // A segment has been detected as lost, by duplicate acknowledgements or the SACK scoreboard,
+    // entering fast recovery with the given amount of data in flight.
+    fn on_congestion_event(&mut self, flight_size: u32, now: Instant);
+
+    // A duplicate acknowledgement during fast recovery, i.e. another segment has left the network.
+    fn on_duplicate_acknowledgement(&mut self);
+
+    // An acknowledgement during fast recovery of some but not all data that was in flight when it started.
+    fn on_partial_acknowledgement(&mut self, acknowledged: u32);
+
+    // Fast recovery has ended with the given amount of data still in flight.
+    fn on_recovery_exit(&mut self, flight_size: u32);
+
+    // The retransmission timer expired for the first time for a segment, with the given amount of data in flight.
+    fn on_retransmission_timeout(&mut self, flight_size: u32, now: Instant);
+
+    fn clone_box(&self) -> Box<dyn CongestionControl>;
+}
+
+impl Clone for Box<dyn CongestionControl> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+// The congestion window to start with, as specified in https://datatracker.ietf.org/doc/html/rfc5681#section-3.1
+pub fn initial_window(maximum_segment_size: u32) -> u32 {
+    if maximum_segment_size > 2190 {
+        2 * maximum_segment_size
+    } else if maximum_segment_size > 1095 {
+        3 * maximum_segment_size
+    } else {
+        4 * maximum_segment_size
+    }
+}
+
+// The slow start threshold after a loss, half of what was in flight but at least two segments,
+// as specified in https://datatracker.ietf.org/doc/html/rfc5681#section-3.1
+pub fn halved_flight_size(flight_size: u32, maximum_segment_size: u32) -> u32 {
+    (flight_size / 2).max(2 * maximum_segment_size)
+}
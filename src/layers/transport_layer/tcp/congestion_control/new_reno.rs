@@ -0,0 +1,191 @@
+use std::time::{Duration, Instant};
+
+use crate::layers::transport_layer::tcp::congestion_control::{
+    halved_flight_size, initial_window, CongestionControl, CongestionControlAlgorithm,
+};
+
+// Slow start & congestion avoidance as specified in https://datatracker.ietf.org/doc/html/rfc5681#section-3.1
+// with the NewReno modification of fast recovery as specified in https://datatracker.ietf.org/doc/html/rfc6582
+#[derive(Clone, Debug)]
+pub struct NewReno {
+    maximum_segment_size: u32, // SMSS
+    congestion_window: u32,    // cwnd
+    slow_start_threshold: u32, // ssthresh
+    // The bytes acknowledged during congestion avoidance that have not yet grown the congestion window.
+    bytes_acknowledged: u32,
+}
+
+impl NewReno {
+    pub fn new(maximum_segment_size: u16) -> Self {
+        let maximum_segment_size = maximum_segment_size as u32;
+
+        NewReno {
+            maximum_segment_size,
+            congestion_window: initial_window(maximum_segment_size),
+            // As high as possible, so the first loss ends slow start.
+            slow_start_threshold: u32::MAX,
+            bytes_acknowledged: 0,
+        }
+    }
+}
+
+impl CongestionControl for NewReno {
+    fn algorithm(&self) -> CongestionControlAlgorithm {
+        CongestionControlAlgorithm::NewReno
+    }
+
+    fn congestion_window(&self) -> u32 {
+        self.congestion_window
+    }
+
+    fn slow_start_threshold(&self) -> u32 {
+        self.slow_start_threshold
+    }
+
+    fn on_acknowledgement(
+        &mut self,
+        acknowledged: u32,
+        _now: Instant,
+        _round_trip_time: Option<Duration>,
+    ) {
+        // Slow start grows the window by at most one segment per acknowledgement.
+        if self.congestion_window < self.slow_start_threshold {
+            self.congestion_window = self
+                .congestion_window
+                .saturating_add(acknowledged.min(self.maximum_segment_size));
+            return;
+        }
+
+        // Congestion avoidance grows the window by one segment per window of acknowledged data.
+        self.bytes_acknowledged = self.bytes_acknowledged.saturating_add(acknowledged);
+        if self.bytes_acknowledged >= self.congestion_window {
+            self.bytes_acknowledged -= self.congestion_window;
+            self.congestion_window = self
+                .congestion_window
+                .saturating_add(self.maximum_segment_size);
+        }
+    }
+
+    fn on_congestion_event(&mut self, flight_size: u32, _now: Instant) {
+        self.slow_start_threshold = halved_flight_size(flight_size, self.maximum_segment_size);
+        // The three duplicate acknowledgements stand for segments that have left the network.
+        self.congestion_window = self.slow_start_threshold + 3 * self.maximum_segment_size;
+        self.bytes_acknowledged = 0;
+    }
+
+    fn on_duplicate_acknowledgement(&mut self) {
+        self.congestion_window = self
+            .congestion_window
+            .saturating_add(self.maximum_segment_size);
+    }
+
+    fn on_partial_acknowledgement(&mut self, acknowledged: u32) {
+        // Deflate the window by the acknowledged data, making room for the retransmission if it was a full segment.
+        self.congestion_window = self.congestion_window.saturating_sub(acknowledged);
+        if acknowledged >= self.maximum_segment_size {
+            self.congestion_window += self.maximum_segment_size;
+        }
+    }
+
+    fn on_recovery_exit(&mut self, flight_size: u32) {
+        self.congestion_window = self
+            .slow_start_threshold
+            .min(flight_size.max(self.maximum_segment_size) + self.maximum_segment_size);
+    }
+
+    fn on_retransmission_timeout(&mut self, flight_size: u32, _now: Instant) {
+        self.slow_start_threshold = halved_flight_size(flight_size, self.maximum_segment_size);
+        // The loss window, which starts slow start all over again.
+        self.congestion_window = self.maximum_segment_size;
+        self.bytes_acknowledged = 0;
+    }
+
+    fn clone_box(&self) -> Box<dyn CongestionControl> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: u32 = 1000;
+
+    fn new_reno() -> NewReno {
+        NewReno::new(MSS as u16)
+    }
+
+    #[test]
+    fn slow_start_grows_by_at_most_one_segment_per_acknowledgement() {
+        let mut new_reno = new_reno();
+        assert_eq!(new_reno.congestion_window(), 4 * MSS);
+
+        new_reno.on_acknowledgement(MSS / 2, Instant::now(), None);
+        assert_eq!(new_reno.congestion_window(), 4 * MSS + MSS / 2);
+
+        new_reno.on_acknowledgement(3 * MSS, Instant::now(), None);
+        assert_eq!(new_reno.congestion_window(), 5 * MSS + MSS / 2);
+    }
+
+    #[test]
+    fn congestion_avoidance_grows_by_one_segment_per_window() {
+        let mut new_reno = new_reno();
+        new_reno.on_retransmission_timeout(8 * MSS, Instant::now());
+        for _ in 0..3 {
+            new_reno.on_acknowledgement(MSS, Instant::now(), None);
+        }
+        assert_eq!(
+            new_reno.congestion_window(),
+            new_reno.slow_start_threshold()
+        );
+
+        for _ in 0..3 {
+            new_reno.on_acknowledgement(MSS, Instant::now(), None);
+        }
+        assert_eq!(new_reno.congestion_window(), 4 * MSS);
+
+        new_reno.on_acknowledgement(MSS, Instant::now(), None);
+        assert_eq!(new_reno.congestion_window(), 5 * MSS);
+    }
+
+    #[test]
+    fn fast_recovery_inflates_and_deflates_the_window() {
+        let mut new_reno = new_reno();
+        new_reno.on_congestion_event(10 * MSS, Instant::now());
+        assert_eq!(new_reno.slow_start_threshold(), 5 * MSS);
+        assert_eq!(new_reno.congestion_window(), 8 * MSS);
+
+        new_reno.on_duplicate_acknowledgement();
+        assert_eq!(new_reno.congestion_window(), 9 * MSS);
+
+        // A partial acknowledgement of a full segment makes room for the retransmission.
+        new_reno.on_partial_acknowledgement(MSS + MSS / 2);
+        assert_eq!(new_reno.congestion_window(), 8 * MSS + MSS / 2);
+        new_reno.on_partial_acknowledgement(MSS / 2);
+        assert_eq!(new_reno.congestion_window(), 8 * MSS);
+    }
+
+    #[test]
+    fn recovery_exit_sets_the_window_to_min_of_ssthresh_and_flight_size_plus_one_segment() {
+        let mut new_reno = new_reno();
+        new_reno.on_congestion_event(10 * MSS, Instant::now());
+
+        new_reno.on_recovery_exit(2 * MSS);
+        assert_eq!(new_reno.congestion_window(), 3 * MSS);
+
+        new_reno.on_recovery_exit(10 * MSS);
+        assert_eq!(new_reno.congestion_window(), 5 * MSS);
+    }
+
+    #[test]
+    fn retransmission_timeout_collapses_the_window() {
+        let mut new_reno = new_reno();
+        new_reno.on_retransmission_timeout(10 * MSS, Instant::now());
+        assert_eq!(new_reno.congestion_window(), MSS);
+        assert_eq!(new_reno.slow_start_threshold(), 5 * MSS);
+
+        // The slow start threshold is at least two segments.
+        new_reno.on_retransmission_timeout(MSS, Instant::now());
+        assert_eq!(new_reno.slow_start_threshold(), 2 * MSS);
+    }
+}
@@ -0,0 +1,1041 @@
+use std::collections::HashMap;
+use std::task::Waker;
+use std::time::Instant;
+
+use eyre::{Context, ContextCompat};
+use log::{debug, trace};
+
+use crate::common::timers::Timers;
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use crate::layers::transport_layer::tcp::connection_wakers::ConnectionWakers;
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::initial_sequence_number::InitialSequenceNumberGenerator;
+use crate::layers::transport_layer::tcp::keepalive::Keepalive;
+use crate::layers::transport_layer::tcp::listener::{ListenAddress, Listener};
+use crate::layers::transport_layer::tcp::send_sequence::SendSequence;
+use crate::layers::transport_layer::tcp::states::closed::handle_closed_receive;
+use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
+use crate::layers::transport_layer::tcp::states::tcp_state::TcpState;
+use crate::layers::transport_layer::tcp::syn_cookies::{SynCookies, COOKIE_LIFETIME};
+use crate::layers::transport_layer::tcp::tcb::{DEFAULT_MAXIMUM_SEGMENT_SIZE, TCB};
+use crate::layers::transport_layer::tcp::tcp::TCP;
+use crate::layers::transport_layer::tcp::tcp_ip_port_quad::TCPQuad;
+use crate::layers::transport_layer::tcp::tcp_timer::TcpTimer;
+
+// The length of the headers that precede the data of a segment, without any options.
+const IPV4_HEADER_LENGTH: u16 = 20;
+const IPV6_HEADER_LENGTH: u16 = 40;
+const TCP_HEADER_LENGTH: u16 = 20;
+
+// How a connection has ended, so the socket still holding it can tell why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionEnd {
+    Closed,
+    Reset,
+    // The peer did not acknowledge a retransmission or keepalive probe in time.
+    TimedOut,
+}
+
+// All TCP connections of the stack together with the timers they have running.
+// A TCB registers a timer by setting its expiry and cancels it by clearing it,
+// the table keeps track of the changes and hands expired timers back to the TCB.
+pub struct ConnectionTable {
+    connections: HashMap<TCPQuad, TCB>,
+    timers: Timers<(TCPQuad, TcpTimer)>,
+    // The MTU of the interface, which limits the size of the segments we send and receive.
+    maximum_transmission_unit: u16,
+    // The congestion control of the connections we open or accept, unless they choose another.
+    congestion_control: CongestionControlAlgorithm,
+    // The addresses we accept connections on, any SYN to another address is reset.
+    listeners: HashMap<ListenAddress, Listener>,
+    // The connections listeners have accepted that are not established yet, with the listener that accepted them.
+    half_open: HashMap<TCPQuad, ListenAddress>,
+    // Answers the SYNs that do not fit in a full backlog without keeping any state, None to drop them instead.
+    syn_cookies: Option<SynCookies>,
+    // Chooses the ISS of every connection we open or accept.
+    initial_sequence_numbers: InitialSequenceNumberGenerator,
+    // The tasks waiting for something to happen on the connections.
+    wakers: HashMap<TCPQuad, ConnectionWakers>,
+    // How the connections handed to the application have ended, None while they have not.
+    // They are remembered until the application forgets the connection.
+    ends: HashMap<TCPQuad, Option<ConnectionEnd>>,
+    // The data the peer sent on connections handed to the application that have closed before it was read,
+    // kept until the application has read it or forgets the connection.
+    unread: HashMap<TCPQuad, Vec<u8>>,
+}
+
+impl ConnectionTable {
+    pub fn new(
+        maximum_transmission_unit: u16,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> Self {
+        ConnectionTable {
+            connections: HashMap::new(),
+            timers: Timers::new(),
+            maximum_transmission_unit,
+            congestion_control,
+            listeners: HashMap::new(),
+            half_open: HashMap::new(),
+            syn_cookies: Some(SynCookies::new()),
+            initial_sequence_numbers: InitialSequenceNumberGenerator::new(),
+            wakers: HashMap::new(),
+            ends: HashMap::new(),
+            unread: HashMap::new(),
+        }
+    }
+
+    // Replaces how the ISS of new connections is chosen, e.g. to read the time from a different clock.
+    pub fn set_initial_sequence_numbers(
+        &mut self,
+        initial_sequence_numbers: InitialSequenceNumberGenerator,
+    ) {
+        self.initial_sequence_numbers = initial_sequence_numbers;
+    }
+
+    // Enables or disables SYN cookies for the SYNs that do not fit in a full backlog.
+    pub fn set_syn_cookies(&mut self, enabled: bool) {
+        self.syn_cookies = if enabled {
+            Some(SynCookies::new())
+        } else {
+            None
+        };
+    }
+
+    // The congestion control of the connections opened or accepted from now on, unless they choose another.
+    pub fn default_congestion_control(&self) -> CongestionControlAlgorithm {
+        self.congestion_control
+    }
+
+    pub fn set_default_congestion_control(
+        &mut self,
+        congestion_control: CongestionControlAlgorithm,
+    ) {
+        self.congestion_control = congestion_control;
+    }
+
+    // Sets the keepalive of the connections the listener accepts from now on, None to not probe them.
+    pub fn set_listener_keepalive(
+        &mut self,
+        address: &ListenAddress,
+        keepalive: Option<Keepalive>,
+    ) -> eyre::Result<()> {
+        self.listeners
+            .get_mut(address)
+            .wrap_err("not listening on address")?
+            .keepalive = keepalive;
+        Ok(())
+    }
+
+    // Sets the congestion control of the connections the listener accepts from now on.
+    pub fn set_listener_congestion_control(
+        &mut self,
+        address: &ListenAddress,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> eyre::Result<()> {
+        self.listeners
+            .get_mut(address)
+            .wrap_err("not listening on address")?
+            .congestion_control = Some(congestion_control);
+        Ok(())
+    }
+
+    // The connection of the quad, None if there is none.
+    pub fn get(&self, quad: &TCPQuad) -> Option<&TCB> {
+        self.connections.get(quad)
+    }
+
+    // How the connection of the quad has ended, None if it has not or was not handed to the application.
+    pub fn connection_end(&self, quad: &TCPQuad) -> Option<ConnectionEnd> {
+        self.ends.get(quad).copied().flatten()
+    }
+
+    // Stops remembering how the connection ends, once the application no longer holds it.
+    pub fn forget(&mut self, quad: &TCPQuad) {
+        self.ends.remove(quad);
+        self.unread.remove(quad);
+    }
+
+    // How much of the data the peer has sent on the connection the application has not read yet,
+    // including what a closed connection left behind.
+    pub fn unread_length(&self, quad: &TCPQuad) -> usize {
+        match self.connections.get(quad) {
+            Some(tcb) => tcb.receive_buffer.len(),
+            None => self.unread.get(quad).map_or(0, Vec::len),
+        }
+    }
+
+    // The MTU of the interface, the largest packet we send or receive.
+    pub fn maximum_transmission_unit(&self) -> u16 {
+        self.maximum_transmission_unit
+    }
+
+    // Accepts connections on the address (OPEN call, passive mode),
+    // keeping at most backlog connections half-open or waiting to be accepted at once.
+    pub fn listen(&mut self, address: ListenAddress, backlog: usize) -> eyre::Result<()> {
+        if self.listeners.contains_key(&address) {
+            eyre::bail!("already listening on {:?}", address);
+        }
+
+        self.listeners.insert(address, Listener::new(backlog));
+        Ok(())
+    }
+
+    // Stops accepting connections on the address,
+    // returning the resets of the connections that were half-open or waiting to be accepted.
+    pub fn unlisten(&mut self, address: &ListenAddress) -> eyre::Result<Vec<(TCPQuad, TCP)>> {
+        let listener = self
+            .listeners
+            .remove(address)
+            .wrap_err("not listening on address")?;
+        let half_open: Vec<TCPQuad> = self
+            .half_open
+            .iter()
+            .filter(|(_, other)| *other == address)
+            .map(|(quad, _)| quad.clone())
+            .collect();
+
+        let mut resets = vec![];
+        for quad in listener.accept_queue.into_iter().chain(half_open) {
+            if let Some(tcb) = self.connections.get(&quad) {
+                let reset =
+                    tcb.create_segment(tcb.send_sequence.next, ControlBits::get_rst(), vec![]);
+                self.remove_connection(&quad, ConnectionEnd::Reset);
+                resets.push((quad, reset));
+            }
+        }
+
+        Ok(resets)
+    }
+
+    // Takes the oldest established connection waiting to be accepted on the address, None if there is none.
+    pub fn accept(&mut self, address: &ListenAddress) -> eyre::Result<Option<TCPQuad>> {
+        let listener = self
+            .listeners
+            .get_mut(address)
+            .wrap_err("not listening on address")?;
+
+        // Connections that have been closed since are skipped.
+        while let Some(quad) = listener.accept_queue.pop_front() {
+            if self.connections.contains_key(&quad) {
+                self.ends.insert(quad.clone(), None);
+                return Ok(Some(quad));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Wakes the task up once data arrives on the connection or the peer closes its side.
+    pub fn register_read_waker(&mut self, quad: &TCPQuad, waker: Waker) {
+        self.wakers.entry(quad.clone()).or_default().read = Some(waker);
+    }
+
+    // Wakes the task up once there is room to write on the connection or its state changes.
+    pub fn register_write_waker(&mut self, quad: &TCPQuad, waker: Waker) {
+        self.wakers.entry(quad.clone()).or_default().write = Some(waker);
+    }
+
+    // Wakes the task up once a connection is established on the listener.
+    pub fn register_accept_waker(
+        &mut self,
+        address: &ListenAddress,
+        waker: Waker,
+    ) -> eyre::Result<()> {
+        self.listeners
+            .get_mut(address)
+            .wrap_err("not listening on address")?
+            .accept_waker = Some(waker);
+        Ok(())
+    }
+
+    // Handles a segment received for the quad, returning the segments to respond with.
+    pub fn on_segment_received(&mut self, quad: TCPQuad, segment: &TCP) -> Vec<TCP> {
+        // Segments that do not belong to a connection and do not request one from a listener are reset.
+        let requests_connection =
+            segment.control_bits.syn && !segment.control_bits.ack && !segment.control_bits.rst;
+        if !self.connections.contains_key(&quad) {
+            if !requests_connection {
+                if let Some(segments) = self.accept_syn_cookie(quad.clone(), segment) {
+                    return segments;
+                }
+
+                debug!("no connection for {:?}, resetting", quad);
+                return handle_closed_receive(segment).into_iter().collect();
+            }
+
+            let address = match self.find_listener(&quad) {
+                Some(address) => address,
+                None => {
+                    debug!("no listener for {:?}, resetting", quad);
+                    return handle_closed_receive(segment).into_iter().collect();
+                }
+            };
+
+            if self.is_syn_backlog_full(&address) {
+                return self
+                    .send_syn_cookie(&address, quad, segment)
+                    .into_iter()
+                    .collect();
+            }
+
+            self.half_open.insert(quad.clone(), address);
+        }
+
+        let result = match self.connections.get(&quad) {
+            Some(tcb) => tcb.on_packet_received(segment),
+            None => self.listening_tcb(&quad).on_packet_received(segment),
+        };
+        let result = match result.wrap_err("receiving TCP package") {
+            Ok(r) => r,
+            Err(err) => {
+                debug!("dropping segment of {:?}: {:#}", quad, err);
+                if !self.connections.contains_key(&quad) {
+                    self.half_open.remove(&quad);
+                }
+                return vec![];
+            }
+        };
+
+        self.apply_state_change(quad, result)
+    }
+
+    // The listener to accept a connection of the quad, one bound to the exact address over one bound to any.
+    fn find_listener(&self, quad: &TCPQuad) -> Option<ListenAddress> {
+        self.listeners
+            .keys()
+            .filter(|address| address.matches(quad))
+            .max_by_key(|address| address.ip.is_some())
+            .cloned()
+    }
+
+    // The TCB in the LISTEN state that a listener opens a connection of the quad from,
+    // with the options of the listener that accepted the quad.
+    fn listening_tcb(&self, quad: &TCPQuad) -> TCB {
+        let listener = self
+            .half_open
+            .get(quad)
+            .and_then(|address| self.listeners.get(address));
+
+        TCB {
+            send_sequence: SendSequence {
+                initial_send_sequence: self.initial_sequence_numbers.generate(quad),
+                ..SendSequence::default()
+            },
+            receive_maximum_segment_size: self.maximum_segment_size(quad),
+            congestion_control: self
+                .listener_congestion_control(listener)
+                .create(DEFAULT_MAXIMUM_SEGMENT_SIZE),
+            keepalive: listener.and_then(|listener| listener.keepalive),
+            ..TCB::default()
+        }
+    }
+
+    // The congestion control of the connections the listener accepts.
+    fn listener_congestion_control(
+        &self,
+        listener: Option<&Listener>,
+    ) -> CongestionControlAlgorithm {
+        listener
+            .and_then(|listener| listener.congestion_control)
+            .unwrap_or(self.congestion_control)
+    }
+
+    // Whether the listener already has as many connections half-open or waiting to be accepted as its backlog allows.
+    fn is_syn_backlog_full(&self, address: &ListenAddress) -> bool {
+        let listener = match self.listeners.get(address) {
+            Some(listener) => listener,
+            None => return true,
+        };
+        let half_open = self
+            .half_open
+            .values()
+            .filter(|other| *other == address)
+            .count();
+
+        half_open + listener.accept_queue.len() >= listener.backlog
+    }
+
+    // Answers a SYN that does not fit in the backlog with a SYN-ACK carrying a SYN cookie as its ISS,
+    // remembering the overflow of the listener, or drops it if SYN cookies are disabled.
+    fn send_syn_cookie(
+        &mut self,
+        address: &ListenAddress,
+        quad: TCPQuad,
+        segment: &TCP,
+    ) -> Option<TCP> {
+        let syn_cookies = match &self.syn_cookies {
+            Some(syn_cookies) => syn_cookies,
+            None => {
+                debug!("SYN backlog full, dropping SYN");
+                return None;
+            }
+        };
+
+        let receive_maximum_segment_size = self.maximum_segment_size(&quad);
+        let listener = TCB {
+            receive_maximum_segment_size,
+            ..TCB::default()
+        };
+        let now = Instant::now();
+        let (cookie, send_maximum_segment_size) = syn_cookies.encode(
+            &quad,
+            segment.sequence_number,
+            listener.negotiate_maximum_segment_size(segment),
+            now,
+        );
+        if let Some(listener) = self.listeners.get_mut(address) {
+            listener.last_overflow = Some(now);
+        }
+
+        debug!("SYN backlog full, answering with a SYN cookie");
+        let tcb = TCB::from_syn_cookie(
+            &quad,
+            cookie,
+            segment.sequence_number,
+            send_maximum_segment_size,
+            receive_maximum_segment_size,
+            self.congestion_control,
+        );
+        Some(tcb.create_segment(cookie, ControlBits::get_syn_ack(), vec![]))
+    }
+
+    // Reconstructs the connection from the SYN cookie the segment acknowledges and handles the segment on it,
+    // returning the segments to respond with, or None if the segment does not return a valid SYN cookie.
+    // Cookies are only checked while the listener may have handed some out, otherwise every stray ACK
+    // would be a chance to guess one. A valid cookie that no longer fits in the backlog is reset.
+    fn accept_syn_cookie(&mut self, quad: TCPQuad, segment: &TCP) -> Option<Vec<TCP>> {
+        let control_bits = &segment.control_bits;
+        if !control_bits.ack || control_bits.syn || control_bits.rst {
+            return None;
+        }
+
+        let address = self.find_listener(&quad)?;
+        let now = Instant::now();
+        let overflowed = self.listeners.get(&address)?.last_overflow;
+        if overflowed.is_none_or(|overflowed| now.duration_since(overflowed) > COOKIE_LIFETIME) {
+            return None;
+        }
+
+        // The ACK of the SYN-ACK is the first segment after the SYN.
+        let cookie = segment.acknowledgement_number - 1;
+        let peer_initial_sequence = segment.sequence_number - 1;
+        let send_maximum_segment_size =
+            self.syn_cookies
+                .as_ref()?
+                .decode(&quad, peer_initial_sequence, cookie, now)?;
+
+        if self.is_syn_backlog_full(&address) {
+            debug!("valid SYN cookie, but the backlog is full");
+            return Some(handle_closed_receive(segment).into_iter().collect());
+        }
+
+        debug!("valid SYN cookie, reconstructing connection");
+        let listener = self.listeners.get(&address);
+        let tcb = TCB {
+            keepalive: listener.and_then(|listener| listener.keepalive),
+            ..TCB::from_syn_cookie(
+                &quad,
+                cookie,
+                peer_initial_sequence,
+                send_maximum_segment_size,
+                self.maximum_segment_size(&quad),
+                self.listener_congestion_control(listener),
+            )
+        };
+
+        let state_change = match tcb
+            .on_packet_received(segment)
+            .wrap_err("receiving TCP package")
+        {
+            Ok(state_change) => state_change,
+            Err(err) => {
+                debug!("dropping segment of {:?}: {:#}", quad, err);
+                return Some(vec![]);
+            }
+        };
+
+        self.half_open.insert(quad.clone(), address);
+        Some(self.apply_state_change(quad, state_change))
+    }
+
+    // Actively opens a connection to the remote end of the quad using the given congestion control,
+    // returning the initial SYN.
+    pub fn connect(
+        &mut self,
+        quad: TCPQuad,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> eyre::Result<Vec<TCP>> {
+        if self.connections.contains_key(&quad) {
+            eyre::bail!("connection already exists");
+        }
+
+        let maximum_segment_size = self.maximum_segment_size(&quad);
+        let initial_send_sequence = self.initial_sequence_numbers.generate(&quad);
+        let state_change = TCB::connect(
+            &quad,
+            initial_send_sequence,
+            maximum_segment_size,
+            congestion_control,
+        );
+        self.ends.insert(quad.clone(), None);
+        Ok(self.apply_state_change(quad, state_change))
+    }
+
+    // Queues data to be sent on the connection, returning the segments that can be sent right away.
+    pub fn write(&mut self, quad: TCPQuad, data: &[u8]) -> eyre::Result<Vec<TCP>> {
+        let state_change = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .write(data)
+            .wrap_err("writing to connection")?;
+
+        Ok(self.apply_state_change(quad, state_change))
+    }
+
+    // Queues urgent data to be sent on the connection, returning the segments that can be sent right away.
+    pub fn write_urgent(&mut self, quad: TCPQuad, data: &[u8]) -> eyre::Result<Vec<TCP>> {
+        let state_change = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .write_urgent(data)
+            .wrap_err("writing urgent data to connection")?;
+
+        Ok(self.apply_state_change(quad, state_change))
+    }
+
+    // Closes our side of the connection, returning the segments to send,
+    // which end with the FIN once all data written before has been sent.
+    pub fn close(&mut self, quad: TCPQuad) -> eyre::Result<Vec<TCP>> {
+        let state_change = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .close()
+            .wrap_err("closing connection")?;
+
+        Ok(self.apply_state_change(quad, state_change))
+    }
+
+    // Reads up to length bytes the peer has sent on the connection,
+    // returning them together with any window update to send.
+    // Once the connection has closed this reads what it left behind.
+    pub fn read(&mut self, quad: TCPQuad, length: usize) -> eyre::Result<(Vec<u8>, Vec<TCP>)> {
+        if !self.connections.contains_key(&quad) {
+            if let Some(unread) = self.unread.get_mut(&quad) {
+                let length = length.min(unread.len());
+                return Ok((unread.drain(..length).collect(), vec![]));
+            }
+        }
+
+        let (data, state_change) = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .read(length)
+            .wrap_err("reading from connection")?;
+
+        Ok((data, self.apply_state_change(quad, state_change)))
+    }
+
+    // Reads the byte of urgent data the peer has sent out of band on the connection, if there is one.
+    pub fn read_urgent(&mut self, quad: TCPQuad) -> eyre::Result<Option<u8>> {
+        let (data, state_change) = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .read_urgent()
+            .wrap_err("reading urgent data from connection")?;
+
+        self.apply_state_change(quad, state_change);
+        Ok(data)
+    }
+
+    // Enables keepalive for the connection with the given settings, or disables it with None.
+    pub fn set_keepalive(
+        &mut self,
+        quad: TCPQuad,
+        keepalive: Option<Keepalive>,
+    ) -> eyre::Result<()> {
+        let state_change = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .set_keepalive(keepalive);
+
+        self.apply_state_change(quad, state_change);
+        Ok(())
+    }
+
+    // Disables or re-enables Nagle's algorithm for the connection, returning the segments it no longer holds back.
+    pub fn set_no_delay(&mut self, quad: TCPQuad, no_delay: bool) -> eyre::Result<Vec<TCP>> {
+        let state_change = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .set_no_delay(no_delay);
+
+        Ok(self.apply_state_change(quad, state_change))
+    }
+
+    // Switches the connection to the given congestion control, returning the segments its window now allows.
+    pub fn set_congestion_control(
+        &mut self,
+        quad: TCPQuad,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> eyre::Result<Vec<TCP>> {
+        let state_change = self
+            .connections
+            .get(&quad)
+            .wrap_err("connection does not exist")?
+            .set_congestion_control(congestion_control);
+
+        Ok(self.apply_state_change(quad, state_change))
+    }
+
+    // The largest segment that fits in a single packet on the interface, which we advertise as our MSS.
+    // As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.7.1
+    fn maximum_segment_size(&self, quad: &TCPQuad) -> u16 {
+        let ip_header_length = match quad.dst_ip {
+            IPAddress::V4(_) => IPV4_HEADER_LENGTH,
+            IPAddress::V6(_) => IPV6_HEADER_LENGTH,
+        };
+
+        self.maximum_transmission_unit
+            .saturating_sub(ip_header_length + TCP_HEADER_LENGTH)
+    }
+
+    // When the table next needs to handle an expired timer.
+    pub fn next_timer_expiry(&self) -> Option<Instant> {
+        self.timers.next_expiry()
+    }
+
+    // Handles all timers that have expired by now, returning the segments to send.
+    pub fn on_timers_expired(&mut self, now: Instant) -> Vec<(TCPQuad, TCP)> {
+        let mut segments = vec![];
+
+        while let Some((quad, timer)) = self.timers.pop_expired(now) {
+            let tcb = match self.connections.get(&quad) {
+                Some(tcb) => tcb,
+                None => continue, // The connection has been closed since.
+            };
+
+            // The timer might have been cancelled or restarted since it was registered.
+            match tcb.timer_expiry(timer) {
+                Some(expiry) if expiry <= now => {}
+                _ => continue,
+            }
+
+            trace!("{} timer expired for {:?}", timer, quad);
+
+            let state_change = tcb.on_timer_expired(timer);
+            for segment in self.apply_state_change(quad.clone(), state_change) {
+                segments.push((quad.clone(), segment));
+            }
+        }
+
+        segments
+    }
+
+    // Updates the table according to the state change, returning the segments to send.
+    fn apply_state_change(&mut self, quad: TCPQuad, state_change: TCPStateChange) -> Vec<TCP> {
+        let (tcb, segments) = match state_change {
+            TCPStateChange::WithResponse(new_tcb, new_tcp) => (new_tcb, vec![new_tcp]),
+            TCPStateChange::WithResponses(new_tcb, new_tcps) => (new_tcb, new_tcps),
+            TCPStateChange::NoResponse(new_tcb) => (new_tcb, vec![]),
+            TCPStateChange::Closed => {
+                trace!("{:?} closed", quad);
+                self.remove_connection(&quad, ConnectionEnd::Closed);
+                return vec![];
+            }
+            TCPStateChange::Reset(reset) => {
+                trace!("{:?} reset", quad);
+                self.remove_connection(&quad, ConnectionEnd::Reset);
+                return reset.into_iter().collect();
+            }
+            TCPStateChange::TimedOut => {
+                trace!("{:?} timed out", quad);
+                self.remove_connection(&quad, ConnectionEnd::TimedOut);
+                return vec![];
+            }
+        };
+
+        trace!("{:?} now in state {}", quad, tcb.state);
+
+        // A connection a listener accepted waits for the application to accept it once it is established.
+        if tcb.state != TcpState::SynReceived {
+            if let Some(address) = self.half_open.remove(&quad) {
+                if let Some(listener) = self.listeners.get_mut(&address) {
+                    listener.accept_queue.push_back(quad.clone());
+                    if let Some(waker) = listener.accept_waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+        }
+
+        // Register any timer the TCB has started or restarted.
+        for timer in TcpTimer::ALL {
+            let previous = self
+                .connections
+                .get(&quad)
+                .and_then(|previous| previous.timer_expiry(timer));
+
+            if let Some(expiry) = tcb.timer_expiry(timer) {
+                if previous != Some(expiry) {
+                    self.timers.register(expiry, (quad.clone(), timer));
+                }
+            }
+        }
+
+        // Wake the tasks up that are waiting for what has changed.
+        if let (Some(previous), Some(wakers)) =
+            (self.connections.get(&quad), self.wakers.get_mut(&quad))
+        {
+            let state_changed = tcb.state != previous.state;
+            let data_arrived = tcb.receive_buffer.len() > previous.receive_buffer.len()
+                || (tcb.urgent_data.is_some() && previous.urgent_data.is_none());
+            let window_opened = tcb.send_buffer.len() < previous.send_buffer.len()
+                || tcb.send_sequence.window > previous.send_sequence.window;
+
+            if data_arrived || state_changed {
+                wakers.wake_read();
+            }
+            if window_opened || state_changed {
+                wakers.wake_write();
+            }
+        }
+
+        self.connections.insert(quad, tcb);
+        segments
+    }
+
+    // Wakes up every task waiting for a connection or listener, e.g. because the stack has stopped.
+    pub fn wake_all(&mut self) {
+        for wakers in self.wakers.values_mut() {
+            wakers.wake_read();
+            wakers.wake_write();
+        }
+        for listener in self.listeners.values_mut() {
+            if let Some(waker) = listener.accept_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    // Removes the connection, waking up every task waiting for it.
+    // The data a closed connection leaves behind stays readable, a reset discards it.
+    fn remove_connection(&mut self, quad: &TCPQuad, end: ConnectionEnd) {
+        let tcb = self.connections.remove(quad);
+        if let Some(ended) = self.ends.get_mut(quad) {
+            *ended = Some(end);
+
+            let receive_buffer = tcb.map(|tcb| tcb.receive_buffer).unwrap_or_default();
+            if end == ConnectionEnd::Closed && !receive_buffer.is_empty() {
+                self.unread.insert(quad.clone(), receive_buffer);
+            }
+        }
+        self.half_open.remove(quad);
+        if let Some(mut wakers) = self.wakers.remove(quad) {
+            wakers.wake_read();
+            wakers.wake_write();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::layers::ip_layer::ipv4::ipv4_address::IPAddressV4;
+    use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+
+    fn quad() -> TCPQuad {
+        TCPQuad {
+            src_ip: IPAddressV4(0x0a000002).into(),
+            dst_ip: IPAddressV4(0x0a000001).into(),
+            src_port: 80,
+            dst_port: 50000,
+        }
+    }
+
+    fn connect(table: &mut ConnectionTable) -> TCP {
+        let mut segments = table
+            .connect(quad(), CongestionControlAlgorithm::NewReno)
+            .unwrap();
+        segments.remove(0)
+    }
+
+    #[test]
+    fn unanswered_syn_times_out() {
+        let mut table = ConnectionTable::new(1500, CongestionControlAlgorithm::NewReno);
+        connect(&mut table);
+
+        let mut now = Instant::now();
+        for _ in 0..100 {
+            if table.get(&quad()).is_none() {
+                break;
+            }
+            now += Duration::from_secs(24 * 60 * 60);
+            table.on_timers_expired(now);
+        }
+
+        assert!(table.get(&quad()).is_none());
+        assert_eq!(table.connection_end(&quad()), Some(ConnectionEnd::TimedOut));
+    }
+
+    #[test]
+    fn refused_syn_is_reset_until_forgotten() {
+        let mut table = ConnectionTable::new(1500, CongestionControlAlgorithm::NewReno);
+        let syn = connect(&mut table);
+        assert_eq!(table.connection_end(&quad()), None);
+
+        let reset = TCP {
+            src_port: syn.dst_port,
+            dst_port: syn.src_port,
+            sequence_number: SequenceNumber(0),
+            acknowledgement_number: syn.sequence_number + 1,
+            control_bits: ControlBits::get_rst_ack(),
+            options: vec![],
+            data: vec![],
+            ..syn
+        };
+        table.on_segment_received(quad(), &reset);
+
+        assert!(table.get(&quad()).is_none());
+        assert_eq!(table.connection_end(&quad()), Some(ConnectionEnd::Reset));
+
+        table.forget(&quad());
+        assert_eq!(table.connection_end(&quad()), None);
+    }
+
+    const LISTEN_PORT: u16 = 80;
+
+    fn listening_table(backlog: usize) -> ConnectionTable {
+        let mut table = ConnectionTable::new(1500, CongestionControlAlgorithm::NewReno);
+        table
+            .listen(
+                ListenAddress {
+                    ip: None,
+                    port: LISTEN_PORT,
+                },
+                backlog,
+            )
+            .unwrap();
+        table
+    }
+
+    // The quad of the connection a peer opens from the port to the listener, as the table sees it.
+    fn peer_quad(port: u16) -> TCPQuad {
+        TCPQuad {
+            src_ip: IPAddressV4(0x0a000002).into(),
+            dst_ip: IPAddressV4(0x0a000001).into(),
+            src_port: port,
+            dst_port: LISTEN_PORT,
+        }
+    }
+
+    // Splits a state change of the peer's TCB into the TCB it leaves and the segments it sends.
+    fn peer_sends(state_change: TCPStateChange) -> (TCB, Vec<TCP>) {
+        match state_change {
+            TCPStateChange::WithResponse(tcb, segment) => (tcb, vec![segment]),
+            TCPStateChange::WithResponses(tcb, segments) => (tcb, segments),
+            TCPStateChange::NoResponse(tcb) => (tcb, vec![]),
+            _ => panic!("peer connection ended"),
+        }
+    }
+
+    // Has the peer's TCB handle the segment, returning the TCB it leaves and the segments it responds with.
+    fn peer_receive(tcb: &TCB, segment: &TCP) -> (TCB, Vec<TCP>) {
+        peer_sends(tcb.on_packet_received(segment).unwrap())
+    }
+
+    // Opens a connection from the port of the peer, returning the peer's TCB and the SYN it sends.
+    fn peer_syn(port: u16) -> (TCB, TCP) {
+        let quad = peer_quad(port);
+        let peer_view = TCPQuad {
+            src_ip: quad.dst_ip,
+            dst_ip: quad.src_ip,
+            src_port: quad.dst_port,
+            dst_port: quad.src_port,
+        };
+        match TCB::connect(
+            &peer_view,
+            SequenceNumber(1000),
+            1460,
+            CongestionControlAlgorithm::NewReno,
+        ) {
+            TCPStateChange::WithResponse(tcb, syn) => (tcb, syn),
+            _ => panic!("connect sends a single SYN"),
+        }
+    }
+
+    #[test]
+    fn syn_cookie_is_reset_until_it_fits_in_the_backlog() {
+        let mut table = listening_table(1);
+        let (first, syn) = peer_syn(50000);
+        let syn_ack = table.on_segment_received(peer_quad(50000), &syn);
+
+        // The second SYN overflows the backlog and is answered with a cookie.
+        let (second, syn) = peer_syn(50001);
+        let cookie = table.on_segment_received(peer_quad(50001), &syn);
+        assert!(table.half_open.get(&peer_quad(50001)).is_none());
+        let (_, ack) = peer_receive(&second, &cookie[0]);
+
+        let reset = table.on_segment_received(peer_quad(50001), &ack[0]);
+        assert_eq!(reset.len(), 1);
+        assert!(reset[0].control_bits.rst);
+        assert!(table.get(&peer_quad(50001)).is_none());
+
+        // Once the first connection is established & accepted the cookie fits.
+        let (_, first_ack) = peer_receive(&first, &syn_ack[0]);
+        table.on_segment_received(peer_quad(50000), &first_ack[0]);
+        let address = table.find_listener(&peer_quad(50000)).unwrap();
+        assert_eq!(table.accept(&address).unwrap(), Some(peer_quad(50000)));
+
+        assert!(table
+            .on_segment_received(peer_quad(50001), &ack[0])
+            .is_empty());
+        assert_eq!(
+            table.get(&peer_quad(50001)).map(|tcb| &tcb.state),
+            Some(&TcpState::Established)
+        );
+        assert_eq!(table.accept(&address).unwrap(), Some(peer_quad(50001)));
+    }
+
+    #[test]
+    fn syn_cookies_are_not_checked_without_an_overflow() {
+        let mut table = listening_table(1);
+        let (_, syn) = peer_syn(50000);
+        let (cookie, _) = table.syn_cookies.as_ref().unwrap().encode(
+            &peer_quad(50000),
+            syn.sequence_number,
+            1460,
+            Instant::now(),
+        );
+
+        // A valid cookie the listener never handed out is a stray ACK.
+        let forged = TCP {
+            sequence_number: syn.sequence_number + 1,
+            acknowledgement_number: cookie + 1,
+            control_bits: ControlBits::get_ack(),
+            options: vec![],
+            ..syn
+        };
+        let reset = table.on_segment_received(peer_quad(50000), &forged);
+        assert_eq!(reset.len(), 1);
+        assert!(reset[0].control_bits.rst);
+        assert!(table.get(&peer_quad(50000)).is_none());
+    }
+
+    #[test]
+    fn exact_address_is_preferred_over_any_address() {
+        let mut table = listening_table(1);
+        let exact = ListenAddress {
+            ip: Some(peer_quad(50000).dst_ip),
+            port: LISTEN_PORT,
+        };
+        table.listen(exact.clone(), 1).unwrap();
+
+        let (peer, syn) = peer_syn(50000);
+        let syn_ack = table.on_segment_received(peer_quad(50000), &syn);
+        let (_, ack) = peer_receive(&peer, &syn_ack[0]);
+        table.on_segment_received(peer_quad(50000), &ack[0]);
+
+        let any = ListenAddress {
+            ip: None,
+            port: LISTEN_PORT,
+        };
+        assert_eq!(table.accept(&any).unwrap(), None);
+        assert_eq!(table.accept(&exact).unwrap(), Some(peer_quad(50000)));
+    }
+
+    #[test]
+    fn syn_to_a_port_without_listener_is_reset() {
+        let mut table = listening_table(1);
+        let (_, syn) = peer_syn(50000);
+        let quad = TCPQuad {
+            dst_port: LISTEN_PORT + 1,
+            ..peer_quad(50000)
+        };
+        let syn = TCP {
+            dst_port: quad.dst_port,
+            ..syn
+        };
+
+        let reset = table.on_segment_received(quad.clone(), &syn);
+        assert_eq!(reset.len(), 1);
+        assert!(reset[0].control_bits.rst);
+        assert_eq!(reset[0].acknowledgement_number, syn.sequence_number + 1);
+        assert!(table.get(&quad).is_none());
+    }
+
+    #[test]
+    fn closed_connection_leaves_unread_data_behind() {
+        let mut table = listening_table(1);
+        let (peer, syn) = peer_syn(50000);
+        let syn_ack = table.on_segment_received(peer_quad(50000), &syn);
+        let (peer, ack) = peer_receive(&peer, &syn_ack[0]);
+        table.on_segment_received(peer_quad(50000), &ack[0]);
+        let address = table.find_listener(&peer_quad(50000)).unwrap();
+        assert_eq!(table.accept(&address).unwrap(), Some(peer_quad(50000)));
+
+        // The peer sends data & closes its side, which we close as well without reading the data.
+        let (peer, data) = peer_sends(peer.write(b"hello").unwrap());
+        let (peer, fin) = peer_sends(peer.close().unwrap());
+        for segment in data.iter().chain(&fin) {
+            table.on_segment_received(peer_quad(50000), segment);
+        }
+        let fin = table.close(peer_quad(50000)).unwrap();
+        let (_, ack) = peer_receive(&peer, fin.last().unwrap());
+        table.on_segment_received(peer_quad(50000), &ack[0]);
+
+        assert!(table.get(&peer_quad(50000)).is_none());
+        assert_eq!(
+            table.connection_end(&peer_quad(50000)),
+            Some(ConnectionEnd::Closed)
+        );
+        assert_eq!(table.unread_length(&peer_quad(50000)), 5);
+
+        let (data, segments) = table.read(peer_quad(50000), 3).unwrap();
+        assert_eq!(data, b"hel");
+        assert!(segments.is_empty());
+        let (data, _) = table.read(peer_quad(50000), 3).unwrap();
+        assert_eq!(data, b"lo");
+        assert_eq!(table.unread_length(&peer_quad(50000)), 0);
+
+        table.forget(&peer_quad(50000));
+        assert!(table.unread.is_empty());
+    }
+
+    #[test]
+    fn listener_options_apply_to_accepted_connections() {
+        let mut table = listening_table(1);
+        let address = ListenAddress {
+            ip: None,
+            port: LISTEN_PORT,
+        };
+        table
+            .set_listener_keepalive(&address, Some(Keepalive::default()))
+            .unwrap();
+        table
+            .set_listener_congestion_control(&address, CongestionControlAlgorithm::Cubic)
+            .unwrap();
+
+        let (peer, syn) = peer_syn(50000);
+        let syn_ack = table.on_segment_received(peer_quad(50000), &syn);
+        let (_, ack) = peer_receive(&peer, &syn_ack[0]);
+        table.on_segment_received(peer_quad(50000), &ack[0]);
+
+        let tcb = table.get(&peer_quad(50000)).unwrap();
+        assert_eq!(tcb.keepalive, Some(Keepalive::default()));
+        assert!(tcb.keepalive_expiry.is_some());
+        assert_eq!(
+            tcb.congestion_control.algorithm(),
+            CongestionControlAlgorithm::Cubic
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use std::task::Waker;
+
+// The tasks waiting for something to happen on a connection, each woken once it does.
+#[derive(Debug, Default)]
+pub struct ConnectionWakers {
+    // Waiting for data to arrive or the peer to close its side.
+    pub read: Option<Waker>,
+    // Waiting for room to write, i.e. for the peer to acknowledge data or open its window.
+    pub write: Option<Waker>,
+}
+
+impl ConnectionWakers {
+    pub fn wake_read(&mut self) {
+        if let Some(waker) = self.read.take() {
+            waker.wake();
+        }
+    }
+
+    pub fn wake_write(&mut self) {
+        if let Some(waker) = self.write.take() {
+            waker.wake();
+        }
+    }
+}
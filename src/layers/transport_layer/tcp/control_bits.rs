@@ -126,6 +126,50 @@ impl ControlBits {
             fin: false,
         }
     }
+
+    pub fn get_psh_ack() -> ControlBits {
+        ControlBits {
+            urg: false,
+            ack: true,
+            psh: true,
+            rst: false,
+            syn: false,
+            fin: false,
+        }
+    }
+
+    pub fn get_fin_ack() -> ControlBits {
+        ControlBits {
+            urg: false,
+            ack: true,
+            psh: false,
+            rst: false,
+            syn: false,
+            fin: true,
+        }
+    }
+
+    pub fn get_rst() -> ControlBits {
+        ControlBits {
+            urg: false,
+            ack: false,
+            psh: false,
+            rst: true,
+            syn: false,
+            fin: false,
+        }
+    }
+
+    pub fn get_rst_ack() -> ControlBits {
+        ControlBits {
+            urg: false,
+            ack: true,
+            psh: false,
+            rst: true,
+            syn: false,
+            fin: false,
+        }
+    }
 }
 
 impl Display for ControlBits {
@@ -0,0 +1,126 @@
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::tcp_ip_port_quad::TCPQuad;
+
+// Chooses the initial send sequence numbers (ISS) of our connections, as specified in
+// https://datatracker.ietf.org/doc/html/rfc6528#section-3
+// i.e. ISN = M + F(localip, localport, remoteip, remoteport, secretkey), where M is a clock ticking every
+// 4 microseconds and F a hash keyed with a secret, so the ISNs of a quad keep increasing across its incarnations
+// while the ISNs of other quads can't be predicted from it.
+pub struct InitialSequenceNumberGenerator {
+    // The secret key, chosen anew every time the stack starts.
+    secret: u128,
+    // The time since some fixed point, which the clock component is derived from.
+    clock: Box<dyn Fn() -> Duration + Send>,
+}
+
+impl Default for InitialSequenceNumberGenerator {
+    fn default() -> Self {
+        InitialSequenceNumberGenerator::new()
+    }
+}
+
+impl InitialSequenceNumberGenerator {
+    pub fn new() -> Self {
+        // Every RandomState is seeded randomly.
+        let random = || RandomState::new().build_hasher().finish() as u128;
+        let secret = (random() << 64) | random();
+
+        InitialSequenceNumberGenerator::with_secret_and_clock(
+            secret,
+            Box::new(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("System time set to before UNIX EPOCH!")
+            }),
+        )
+    }
+
+    // Creates a generator keyed with the given secret, reading the time from the given clock instead of the system time.
+    pub fn with_secret_and_clock(secret: u128, clock: Box<dyn Fn() -> Duration + Send>) -> Self {
+        InitialSequenceNumberGenerator { secret, clock }
+    }
+
+    // The ISS for a new connection of the quad.
+    pub fn generate(&self, quad: &TCPQuad) -> SequenceNumber {
+        let clock = ((self.clock)().as_micros() / 4) as u32; // M, should update every 4 microseconds.
+
+        let mut hasher = DefaultHasher::new();
+        self.secret.hash(&mut hasher);
+        quad.hash(&mut hasher);
+
+        SequenceNumber(clock) + hasher.finish() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::ip_layer::ipv4::ipv4_address::IPAddressV4;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    const SECRET: u128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+
+    fn quad(remote_port: u16) -> TCPQuad {
+        TCPQuad {
+            src_ip: IPAddressV4(0x0a000001).into(),
+            dst_ip: IPAddressV4(0x0a000002).into(),
+            src_port: remote_port,
+            dst_port: 80,
+        }
+    }
+
+    // A generator whose clock reads the returned number of microseconds.
+    fn clocked_generator() -> (InitialSequenceNumberGenerator, Arc<AtomicU64>) {
+        let micros = Arc::new(AtomicU64::new(1_000_000));
+        let clock = micros.clone();
+        let generator = InitialSequenceNumberGenerator::with_secret_and_clock(
+            SECRET,
+            Box::new(move || Duration::from_micros(clock.load(Ordering::SeqCst))),
+        );
+
+        (generator, micros)
+    }
+
+    #[test]
+    fn advances_with_the_clock() {
+        let (generator, micros) = clocked_generator();
+        let first = generator.generate(&quad(50000));
+        assert_eq!(generator.generate(&quad(50000)), first);
+
+        // The clock ticks once every 4 microseconds.
+        micros.fetch_add(400, Ordering::SeqCst);
+        assert_eq!(generator.generate(&quad(50000)), first + 100);
+
+        // Wrapping around 2^32 ticks leaves the ISN where it was.
+        micros.fetch_add(4 << 32, Ordering::SeqCst);
+        assert_eq!(generator.generate(&quad(50000)), first + 100);
+    }
+
+    #[test]
+    fn quads_get_different_offsets() {
+        let (generator, _) = clocked_generator();
+        let (same_secret, _) = clocked_generator();
+        let other_secret = InitialSequenceNumberGenerator::with_secret_and_clock(
+            !SECRET,
+            Box::new(|| Duration::from_micros(1_000_000)),
+        );
+
+        assert_ne!(
+            generator.generate(&quad(50000)),
+            generator.generate(&quad(50001))
+        );
+        assert_eq!(
+            generator.generate(&quad(50000)),
+            same_secret.generate(&quad(50000))
+        );
+        assert_ne!(
+            generator.generate(&quad(50000)),
+            other_secret.generate(&quad(50000))
+        );
+    }
+}
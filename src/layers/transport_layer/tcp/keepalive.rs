@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+// How a connection checks whether an idle peer is still there,
+// as specified in https://datatracker.ietf.org/doc/html/rfc1122#section-4.2.3.6
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keepalive {
+    // How long the connection has to be idle before the first probe is sent.
+    pub idle: Duration,
+    // How long to wait for an answer before sending the next probe.
+    pub interval: Duration,
+    // The number of unanswered probes after which the peer is considered dead.
+    pub probes: u32,
+}
+
+impl Default for Keepalive {
+    // The idle time must be at least two hours by default.
+    fn default() -> Self {
+        Keepalive {
+            idle: Duration::from_secs(2 * 60 * 60),
+            interval: Duration::from_secs(75),
+            probes: 9,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+use std::task::Waker;
+use std::time::Instant;
+
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use crate::layers::transport_layer::tcp::keepalive::Keepalive;
+use crate::layers::transport_layer::tcp::tcp_ip_port_quad::TCPQuad;
+
+// The local address & port a listener accepts connections on, any of our addresses if the address is None.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ListenAddress {
+    pub ip: Option<IPAddress>,
+    pub port: u16,
+}
+
+impl ListenAddress {
+    // Whether a connection of the quad is one to accept on this address.
+    pub fn matches(&self, quad: &TCPQuad) -> bool {
+        self.port == quad.dst_port && self.ip.as_ref().is_none_or(|ip| *ip == quad.dst_ip)
+    }
+}
+
+// A passively opened socket (OPEN call, passive mode), which opens a connection for every SYN it accepts.
+// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.1
+#[derive(Debug)]
+pub struct Listener {
+    // The most connections that may be half-open or waiting to be accepted at once.
+    pub backlog: usize,
+    // The established connections the application has not accepted yet, oldest first.
+    pub accept_queue: VecDeque<TCPQuad>,
+    // The task waiting for a connection to accept, woken once one is established.
+    pub accept_waker: Option<Waker>,
+    // When a SYN last did not fit in the backlog and was answered with a SYN cookie, None if none ever was.
+    pub last_overflow: Option<Instant>,
+    // The keepalive of the connections accepted on the listener, None to not probe them.
+    pub keepalive: Option<Keepalive>,
+    // The congestion control of the connections accepted on the listener, None to use the one of the stack.
+    pub congestion_control: Option<CongestionControlAlgorithm>,
+}
+
+impl Listener {
+    pub fn new(backlog: usize) -> Self {
+        Listener {
+            backlog,
+            accept_queue: VecDeque::new(),
+            accept_waker: None,
+            last_overflow: None,
+            keepalive: None,
+            congestion_control: None,
+        }
+    }
+}
@@ -1,7 +1,19 @@
+pub mod congestion_control;
+pub mod connection_table;
+pub mod connection_wakers;
 pub mod control_bits;
+pub mod initial_sequence_number;
+pub mod keepalive;
+pub mod listener;
+pub mod out_of_order_queue;
 pub mod receive_sequence;
+pub mod retransmission_queue;
 pub mod send_sequence;
+pub mod sequence_number;
 pub mod states;
+pub mod syn_cookies;
 pub mod tcb;
 pub mod tcp;
 pub mod tcp_ip_port_quad;
+pub mod tcp_option;
+pub mod tcp_timer;
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::tcp_option::SackBlock;
+
+// Data that arrived ahead of RCV.NXT, waiting for the gap before it to be filled.
+// The queued ranges never overlap, so the data that is next in sequence is always keyed by RCV.NXT exactly.
+#[derive(Clone, Debug, Default)]
+pub struct OutOfOrderQueue {
+    pub segments: HashMap<SequenceNumber, Vec<u8>>,
+    // The sequence number of the latest segment that was queued, reported first in our SACK blocks.
+    pub latest: Option<SequenceNumber>,
+}
+
+impl OutOfOrderQueue {
+    // Queues the part of the data that lies within the receive window and has not been queued before.
+    pub fn insert(
+        &mut self,
+        sequence_number: SequenceNumber,
+        data: &[u8],
+        next: SequenceNumber,
+        window: u32,
+    ) {
+        if sequence_number <= next {
+            return;
+        }
+
+        // Offsets relative to RCV.NXT, which do not wrap within the window.
+        let start = sequence_number - next;
+        let end = (start as u64 + data.len() as u64).min(window as u64) as u32;
+        if start >= end {
+            return;
+        }
+        self.latest = Some(sequence_number);
+
+        // Cut out everything that is already queued, leaving the ranges that are new.
+        let mut new_ranges = vec![(start, end)];
+        for (queued_sequence, queued_data) in &self.segments {
+            let queued_start = *queued_sequence - next;
+            let queued_end = queued_start + queued_data.len() as u32;
+
+            new_ranges = new_ranges
+                .into_iter()
+                .flat_map(|(start, end)| {
+                    if queued_end <= start || end <= queued_start {
+                        return vec![(start, end)];
+                    }
+
+                    let mut remaining = vec![];
+                    if start < queued_start {
+                        remaining.push((start, queued_start));
+                    }
+                    if queued_end < end {
+                        remaining.push((queued_end, end));
+                    }
+                    remaining
+                })
+                .collect();
+        }
+
+        for (range_start, range_end) in new_ranges {
+            let from = (range_start - start) as usize;
+            let to = (range_end - start) as usize;
+            self.segments
+                .insert(next + range_start, data[from..to].to_vec());
+        }
+    }
+
+    // The contiguous blocks of queued data, the block containing the latest segment first and the others in order.
+    pub fn sack_blocks(&self, next: SequenceNumber) -> Vec<SackBlock> {
+        // Offsets relative to RCV.NXT, so the ranges can be sorted.
+        let mut ranges: Vec<(u32, u32)> = self
+            .segments
+            .iter()
+            .filter(|(sequence_number, _)| **sequence_number > next)
+            .map(|(sequence_number, data)| {
+                let start = *sequence_number - next;
+                (start, start + data.len() as u32)
+            })
+            .collect();
+        ranges.sort_unstable();
+
+        let mut blocks: Vec<SackBlock> = vec![];
+        for (start, end) in ranges {
+            match blocks.last_mut() {
+                Some(block) if block.right == next + start => block.right = next + end,
+                _ => blocks.push(SackBlock {
+                    left: next + start,
+                    right: next + end,
+                }),
+            }
+        }
+
+        let latest = self.latest.and_then(|latest| {
+            blocks
+                .iter()
+                .position(|block| latest.is_within(block.left, block.right - block.left))
+        });
+        if let Some(index) = latest {
+            let block = blocks.remove(index);
+            blocks.insert(0, block);
+        }
+
+        blocks
+    }
+
+    // Removes the queued data that is next in sequence now that everything up to RCV.NXT has been received.
+    // Any queued data before RCV.NXT has been received in the meantime and is discarded.
+    pub fn pop_in_sequence(&mut self, next: SequenceNumber) -> Option<Vec<u8>> {
+        let received: Vec<SequenceNumber> = self
+            .segments
+            .keys()
+            .copied()
+            .filter(|sequence_number| *sequence_number < next)
+            .collect();
+
+        for sequence_number in received {
+            if let Some(data) = self.segments.remove(&sequence_number) {
+                // Keep the part of the data that lies beyond RCV.NXT.
+                let already_received = (next - sequence_number) as usize;
+                if already_received < data.len() {
+                    self.segments
+                        .insert(next, data[already_received..].to_vec());
+                }
+            }
+        }
+
+        self.segments.remove(&next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RCV.NXT just before wrapping around.
+    const NEXT: SequenceNumber = SequenceNumber(u32::MAX - 99);
+    const WINDOW: u32 = 1000;
+
+    fn block(left: u32, right: u32) -> SackBlock {
+        SackBlock {
+            left: NEXT + left,
+            right: NEXT + right,
+        }
+    }
+
+    #[test]
+    fn overlapping_data_is_merged_into_one_block() {
+        let mut queue = OutOfOrderQueue::default();
+        queue.insert(NEXT + 10, &[1; 100], NEXT, WINDOW);
+        queue.insert(NEXT + 50, &[2; 100], NEXT, WINDOW);
+        queue.insert(NEXT + 20, &[3; 10], NEXT, WINDOW);
+
+        // The block wraps around 2^32.
+        assert_eq!(queue.sack_blocks(NEXT), vec![block(10, 150)]);
+    }
+
+    #[test]
+    fn adjacent_data_is_merged_and_the_latest_block_comes_first() {
+        let mut queue = OutOfOrderQueue::default();
+        queue.insert(NEXT + 10, &[1; 10], NEXT, WINDOW);
+        queue.insert(NEXT + 20, &[2; 10], NEXT, WINDOW);
+        queue.insert(NEXT + 300, &[3; 10], NEXT, WINDOW);
+        queue.insert(NEXT + 100, &[4; 10], NEXT, WINDOW);
+
+        assert_eq!(
+            queue.sack_blocks(NEXT),
+            vec![block(100, 110), block(10, 30), block(300, 310)]
+        );
+    }
+
+    #[test]
+    fn data_beyond_the_window_is_not_queued() {
+        let mut queue = OutOfOrderQueue::default();
+        queue.insert(NEXT + (WINDOW - 5), &[1; 10], NEXT, WINDOW);
+        queue.insert(NEXT + WINDOW, &[2; 10], NEXT, WINDOW);
+
+        assert_eq!(queue.sack_blocks(NEXT), vec![block(WINDOW - 5, WINDOW)]);
+    }
+
+    #[test]
+    fn filling_the_gap_pops_the_queued_data() {
+        let mut queue = OutOfOrderQueue::default();
+        queue.insert(NEXT + 10, &[1; 100], NEXT, WINDOW);
+
+        let next = NEXT + 20;
+        assert_eq!(queue.pop_in_sequence(next), Some(vec![1; 90]));
+        assert!(queue.sack_blocks(next + 90).is_empty());
+    }
+}
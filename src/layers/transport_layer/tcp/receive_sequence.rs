@@ -1,17 +1,126 @@
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::tcp::TCP;
+
+// The amount of received data we buffer until the application reads it, which bounds the window we advertise.
+pub const RECEIVE_BUFFER_CAPACITY: u32 = 256 * 1024;
+
+// The shift count we scale the receive window we advertise by, allowing windows of up to 8 MiB.
+pub const RECEIVE_WINDOW_SHIFT: u8 = 7;
+
+#[derive(Clone, Debug)]
 pub struct ReceiveSequence {
-    pub next: u32,
-    pub window: u16,
-    pub urgent_pointer: u32, // TODO: Figure out datatype.
-    pub initial_receive_sequence: u32,
+    pub next: SequenceNumber,
+    pub window: u32,
+    pub urgent_pointer: Option<SequenceNumber>, // RCV.UP, the end of the urgent data the peer sends.
+    pub initial_receive_sequence: SequenceNumber,
+}
+
+impl ReceiveSequence {
+    // Whether the segment carries a FIN that is next in sequence, i.e. all data preceding it has been received.
+    pub fn is_fin_in_sequence(&self, segment: &TCP) -> bool {
+        segment.control_bits.fin && segment.sequence_number + segment.data.len() as u32 == self.next
+    }
+
+    // Whether any part of the segment lies within the receive window,
+    // as specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.7.4
+    // A zero window still accepts a segment at RCV.NXT, such as a window probe, for its ACK but not its data.
+    pub fn is_segment_acceptable(&self, segment: &TCP) -> bool {
+        let length = segment.segment_length();
+        let window = self.window;
+        let start = segment.sequence_number;
+
+        match (length, window) {
+            (0, 0) => start == self.next,
+            (0, _) => start.is_within(self.next, window),
+            (_, 0) => start == self.next,
+            (_, _) => {
+                start.is_within(self.next, window)
+                    || (start + (length - 1)).is_within(self.next, window)
+            }
+        }
+    }
 }
 
 impl Default for ReceiveSequence {
     fn default() -> Self {
         ReceiveSequence {
-            next: 0,
+            next: SequenceNumber::default(),
             window: 0,
+            urgent_pointer: None,
+            initial_receive_sequence: SequenceNumber::default()
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+
+    // RCV.NXT just before wrapping around.
+    const NEXT: SequenceNumber = SequenceNumber(u32::MAX - 9);
+
+    fn receive_sequence(window: u32) -> ReceiveSequence {
+        ReceiveSequence {
+            next: NEXT,
+            window,
+            ..ReceiveSequence::default()
+        }
+    }
+
+    fn segment(sequence_number: SequenceNumber, length: usize) -> TCP {
+        TCP {
+            src_port: 1,
+            dst_port: 2,
+            sequence_number,
+            acknowledgement_number: SequenceNumber::default(),
+            data_offset: 5,
+            reserved: 0,
+            control_bits: ControlBits::get_ack(),
+            window: 0,
+            checksum: 0,
             urgent_pointer: 0,
-            initial_receive_sequence: 0
+            options: vec![],
+            data: vec![0; length],
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn empty_segment_zero_window() {
+        let receive_sequence = receive_sequence(0);
+        assert!(receive_sequence.is_segment_acceptable(&segment(NEXT, 0)));
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT + 1, 0)));
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT - 1, 0)));
+    }
+
+    #[test]
+    fn empty_segment_open_window() {
+        let receive_sequence = receive_sequence(20);
+        assert!(receive_sequence.is_segment_acceptable(&segment(NEXT, 0)));
+        // Past the wrap around, still in the window.
+        assert!(receive_sequence.is_segment_acceptable(&segment(NEXT + 19, 0)));
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT + 20, 0)));
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT - 1, 0)));
+    }
+
+    #[test]
+    fn segment_with_data_zero_window() {
+        // Only a segment at RCV.NXT is accepted, for its ACK but not its data.
+        let receive_sequence = receive_sequence(0);
+        assert!(receive_sequence.is_segment_acceptable(&segment(NEXT, 5)));
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT + 1, 5)));
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT - 5, 5)));
+    }
+
+    #[test]
+    fn segment_with_data_open_window() {
+        let receive_sequence = receive_sequence(20);
+        assert!(receive_sequence.is_segment_acceptable(&segment(NEXT, 5)));
+        // Starting before the window, ending in it.
+        assert!(receive_sequence.is_segment_acceptable(&segment(NEXT - 4, 5)));
+        // Starting in the window past the wrap around, ending beyond it.
+        assert!(receive_sequence.is_segment_acceptable(&segment(NEXT + 19, 5)));
+        // Entirely before or beyond the window.
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT - 5, 5)));
+        assert!(!receive_sequence.is_segment_acceptable(&segment(NEXT + 20, 5)));
+    }
+}
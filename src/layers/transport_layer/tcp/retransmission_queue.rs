@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::tcp::TCP;
+use crate::layers::transport_layer::tcp::tcp_option::SackBlock;
+
+// Retransmission timeout bounds & gains as specified in https://datatracker.ietf.org/doc/html/rfc6298#section-2
+const INITIAL_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(60);
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1); // G
+const VARIANCE_MULTIPLIER: u32 = 4; // K
+
+// The number of times a segment is retransmitted before the connection is considered dead.
+pub const MAX_RETRANSMISSIONS: u32 = 15;
+
+// The number of duplicate acknowledgements, or of segments selectively acknowledged after a segment,
+// before the segment is considered lost (DupThresh),
+// as specified in https://datatracker.ietf.org/doc/html/rfc6675#section-2
+pub const DUPLICATE_THRESHOLD: u32 = 3;
+
+// A segment that has been sent but not yet acknowledged by the peer.
+#[derive(Clone, Debug)]
+pub struct UnacknowledgedSegment {
+    pub segment: TCP,
+    pub last_sent: Instant,
+    pub transmissions: u32,
+    // Whether the peer has selectively acknowledged the segment.
+    pub sacked: bool,
+    // Whether the segment has been considered lost and retransmitted because of it.
+    pub lost: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetransmissionQueue {
+    pub segments: VecDeque<UnacknowledgedSegment>,
+    pub smoothed_round_trip_time: Option<Duration>, // SRTT
+    pub round_trip_time_variation: Duration,        // RTTVAR
+    pub retransmission_timeout: Duration,           // RTO
+    // The number of consecutive timeouts without any new acknowledgement.
+    pub retries: u32,
+    // When the retransmission timer expires, None if it is not running.
+    pub expiry: Option<Instant>,
+}
+
+impl RetransmissionQueue {
+    // Adds a segment that was just sent to the queue, unless it occupies no sequence space.
+    pub fn push(&mut self, segment: &TCP, now: Instant) {
+        if segment.segment_length() == 0 {
+            return;
+        }
+
+        match self
+            .segments
+            .iter_mut()
+            .find(|queued| queued.segment.sequence_number == segment.sequence_number)
+        {
+            // We are sending a segment again, e.g. a SYN-ACK in response to a retransmitted SYN.
+            Some(queued) => {
+                queued.last_sent = now;
+                queued.transmissions += 1;
+            }
+            None => self.segments.push_back(UnacknowledgedSegment {
+                segment: segment.clone(),
+                last_sent: now,
+                transmissions: 1,
+                sacked: false,
+                lost: false,
+            }),
+        }
+
+        if self.expiry.is_none() {
+            self.expiry = Some(now + self.retransmission_timeout);
+        }
+    }
+
+    // Removes all segments that are fully acknowledged by SND.UNA.
+    // The round trip time is sampled by timing the acknowledged segments if time_segments is set,
+    // which is not needed when the round trip time is measured with timestamps instead.
+    pub fn acknowledge(
+        &mut self,
+        unacknowledged: SequenceNumber,
+        now: Instant,
+        time_segments: bool,
+    ) {
+        let mut round_trip_time = None;
+        let mut any_acknowledged = false;
+
+        while let Some(queued) = self.segments.front() {
+            let end = queued.segment.sequence_number + queued.segment.segment_length();
+            if unacknowledged < end {
+                break;
+            }
+
+            // Karn's algorithm, the ACK of a retransmitted segment is ambiguous so it can't be sampled.
+            if time_segments && queued.transmissions == 1 {
+                round_trip_time = Some(now.duration_since(queued.last_sent));
+            }
+
+            any_acknowledged = true;
+            self.segments.pop_front();
+        }
+
+        if !any_acknowledged {
+            return;
+        }
+
+        if let Some(round_trip_time) = round_trip_time {
+            self.update_retransmission_timeout(round_trip_time);
+        }
+
+        self.retries = 0;
+        self.expiry = if self.segments.is_empty() {
+            None
+        } else {
+            Some(now + self.retransmission_timeout)
+        };
+    }
+
+    // Updates the scoreboard with the SACK blocks of the peer, returning the segments that are now considered lost.
+    // A segment is lost once DupThresh segments after it have been selectively acknowledged,
+    // as specified in https://datatracker.ietf.org/doc/html/rfc6675#section-4
+    pub fn process_sack(&mut self, blocks: &[SackBlock]) -> Vec<TCP> {
+        for queued in self.segments.iter_mut() {
+            let start = queued.segment.sequence_number;
+            let length = queued.segment.segment_length();
+
+            queued.sacked |= blocks.iter().any(|block| {
+                start.is_within(block.left, block.right - block.left)
+                    && (start + (length - 1)).is_within(block.left, block.right - block.left)
+            });
+        }
+
+        let mut sacked_after = 0;
+        let mut lost = vec![];
+        for queued in self.segments.iter_mut().rev() {
+            if queued.sacked {
+                sacked_after += 1;
+            } else if sacked_after >= DUPLICATE_THRESHOLD && !queued.lost {
+                queued.lost = true;
+                lost.push(queued.segment.clone());
+            }
+        }
+
+        lost.reverse();
+        lost
+    }
+
+    // Marks the first unacknowledged segment as lost, returning it to be retransmitted unless it was lost before.
+    pub fn mark_first_lost(&mut self) -> Option<TCP> {
+        let queued = self.segments.front_mut()?;
+        if queued.lost {
+            return None;
+        }
+
+        queued.lost = true;
+        Some(queued.segment.clone())
+    }
+
+    // Handles an expired retransmission timer, backing off the timeout and returning the segment to retransmit.
+    // Returns None if there is nothing to retransmit.
+    pub fn on_timeout(&mut self, now: Instant) -> Option<TCP> {
+        let timeout = self
+            .retransmission_timeout
+            .checked_mul(2)
+            .unwrap_or(MAX_RETRANSMISSION_TIMEOUT)
+            .min(MAX_RETRANSMISSION_TIMEOUT);
+
+        let queued = match self.segments.front_mut() {
+            Some(queued) => queued,
+            None => {
+                self.expiry = None;
+                return None;
+            }
+        };
+
+        queued.last_sent = now;
+        queued.transmissions += 1;
+
+        self.retries += 1;
+        self.retransmission_timeout = timeout;
+        self.expiry = Some(now + timeout);
+
+        Some(queued.segment.clone())
+    }
+
+    // Whether we have given up on the peer ever acknowledging what we have sent.
+    pub fn is_exhausted(&self) -> bool {
+        self.retries >= MAX_RETRANSMISSIONS
+    }
+
+    // Takes a round trip time measured with timestamps (RTTM) into account,
+    // as specified in https://datatracker.ietf.org/doc/html/rfc7323#section-4
+    pub fn sample_round_trip_time(&mut self, round_trip_time: Duration) {
+        self.update_retransmission_timeout(round_trip_time);
+    }
+
+    // As specified in https://datatracker.ietf.org/doc/html/rfc6298#section-2
+    fn update_retransmission_timeout(&mut self, round_trip_time: Duration) {
+        match self.smoothed_round_trip_time {
+            None => {
+                self.smoothed_round_trip_time = Some(round_trip_time);
+                self.round_trip_time_variation = round_trip_time / 2;
+            }
+            Some(smoothed) => {
+                let difference = smoothed.abs_diff(round_trip_time);
+
+                // RTTVAR <- (1 - 1/4) * RTTVAR + 1/4 * |SRTT - R'|
+                self.round_trip_time_variation =
+                    self.round_trip_time_variation * 3 / 4 + difference / 4;
+                // SRTT <- (1 - 1/8) * SRTT + 1/8 * R'
+                self.smoothed_round_trip_time = Some(smoothed * 7 / 8 + round_trip_time / 8);
+            }
+        }
+
+        let smoothed = self.smoothed_round_trip_time.unwrap_or(round_trip_time);
+        let variance =
+            (self.round_trip_time_variation * VARIANCE_MULTIPLIER).max(CLOCK_GRANULARITY);
+
+        self.retransmission_timeout =
+            (smoothed + variance).clamp(MIN_RETRANSMISSION_TIMEOUT, MAX_RETRANSMISSION_TIMEOUT);
+    }
+}
+
+impl Default for RetransmissionQueue {
+    fn default() -> Self {
+        RetransmissionQueue {
+            segments: VecDeque::new(),
+            smoothed_round_trip_time: None,
+            round_trip_time_variation: Duration::ZERO,
+            retransmission_timeout: INITIAL_RETRANSMISSION_TIMEOUT,
+            retries: 0,
+            expiry: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+
+    // SND.UNA just before wrapping around.
+    const UNACKNOWLEDGED: SequenceNumber = SequenceNumber(u32::MAX - 149);
+    const SEGMENT_SIZE: u32 = 100;
+
+    fn segment(index: u32) -> TCP {
+        TCP {
+            src_port: 1,
+            dst_port: 2,
+            sequence_number: UNACKNOWLEDGED + index * SEGMENT_SIZE,
+            acknowledgement_number: SequenceNumber::default(),
+            data_offset: 5,
+            reserved: 0,
+            control_bits: ControlBits::get_ack(),
+            window: 0,
+            checksum: 0,
+            urgent_pointer: 0,
+            options: vec![],
+            data: vec![0; SEGMENT_SIZE as usize],
+        }
+    }
+
+    // A queue of the given number of segments in flight, starting at SND.UNA.
+    fn queue(segments: u32) -> RetransmissionQueue {
+        let mut queue = RetransmissionQueue::default();
+        let now = Instant::now();
+        for index in 0..segments {
+            queue.push(&segment(index), now);
+        }
+        queue
+    }
+
+    // The block covering the segments from first up to (excluding) last.
+    fn block(first: u32, last: u32) -> SackBlock {
+        SackBlock {
+            left: UNACKNOWLEDGED + first * SEGMENT_SIZE,
+            right: UNACKNOWLEDGED + last * SEGMENT_SIZE,
+        }
+    }
+
+    fn sacked(queue: &RetransmissionQueue) -> Vec<bool> {
+        queue.segments.iter().map(|queued| queued.sacked).collect()
+    }
+
+    #[test]
+    fn marks_segments_covered_by_a_block() {
+        let mut queue = queue(4);
+        // The block wraps around 2^32.
+        let lost = queue.process_sack(&[block(1, 3)]);
+
+        assert!(lost.is_empty());
+        assert_eq!(sacked(&queue), vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn partially_covered_segments_are_not_sacked() {
+        let mut queue = queue(3);
+        let partial = SackBlock {
+            left: UNACKNOWLEDGED + SEGMENT_SIZE + 1,
+            right: UNACKNOWLEDGED + 3 * SEGMENT_SIZE - 1,
+        };
+        queue.process_sack(&[partial]);
+
+        assert_eq!(sacked(&queue), vec![false, false, false]);
+    }
+
+    #[test]
+    fn blocks_are_remembered_across_acknowledgements() {
+        let mut queue = queue(4);
+        queue.process_sack(&[block(3, 4)]);
+        queue.process_sack(&[block(1, 2), block(3, 4)]);
+
+        assert_eq!(sacked(&queue), vec![false, true, false, true]);
+    }
+
+    #[test]
+    fn segment_is_lost_once_dup_thresh_segments_after_it_are_sacked() {
+        let mut queue = queue(5);
+        assert!(queue.process_sack(&[block(1, 3)]).is_empty());
+
+        let lost = queue.process_sack(&[block(1, 4)]);
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].sequence_number, UNACKNOWLEDGED);
+
+        // A lost segment is only retransmitted once.
+        assert!(queue.process_sack(&[block(1, 5)]).is_empty());
+    }
+
+    #[test]
+    fn cumulative_acknowledgement_removes_sacked_segments() {
+        let mut queue = queue(4);
+        queue.process_sack(&[block(2, 4)]);
+        queue.acknowledge(UNACKNOWLEDGED + 3 * SEGMENT_SIZE, Instant::now(), false);
+
+        assert_eq!(sacked(&queue), vec![true]);
+    }
+
+    #[test]
+    fn first_sample_initializes_srtt_and_rttvar() {
+        let mut queue = RetransmissionQueue::default();
+        let sent = Instant::now();
+        queue.push(&segment(0), sent);
+        queue.acknowledge(
+            UNACKNOWLEDGED + SEGMENT_SIZE,
+            sent + Duration::from_millis(100),
+            true,
+        );
+
+        assert_eq!(
+            queue.smoothed_round_trip_time,
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(queue.round_trip_time_variation, Duration::from_millis(50));
+        // SRTT + 4 * RTTVAR is below the minimum.
+        assert_eq!(queue.retransmission_timeout, MIN_RETRANSMISSION_TIMEOUT);
+    }
+
+    #[test]
+    fn later_samples_are_smoothed() {
+        let mut queue = RetransmissionQueue::default();
+        queue.sample_round_trip_time(Duration::from_secs(2));
+        assert_eq!(queue.retransmission_timeout, Duration::from_secs(6));
+
+        queue.sample_round_trip_time(Duration::from_secs(4));
+        // RTTVAR = 3/4 * 1s + 1/4 * |2s - 4s|, SRTT = 7/8 * 2s + 1/8 * 4s
+        assert_eq!(queue.round_trip_time_variation, Duration::from_millis(1250));
+        assert_eq!(
+            queue.smoothed_round_trip_time,
+            Some(Duration::from_millis(2250))
+        );
+        assert_eq!(queue.retransmission_timeout, Duration::from_millis(7250));
+    }
+
+    #[test]
+    fn retransmission_timeout_is_clamped() {
+        let mut queue = RetransmissionQueue::default();
+        queue.sample_round_trip_time(Duration::from_millis(10));
+        assert_eq!(queue.retransmission_timeout, MIN_RETRANSMISSION_TIMEOUT);
+
+        let mut queue = RetransmissionQueue::default();
+        queue.sample_round_trip_time(Duration::from_secs(100));
+        assert_eq!(queue.retransmission_timeout, MAX_RETRANSMISSION_TIMEOUT);
+    }
+
+    #[test]
+    fn timeout_backs_off_up_to_the_maximum() {
+        let mut queue = queue(2);
+        let now = Instant::now();
+
+        let retransmitted = queue.on_timeout(now).unwrap();
+        assert_eq!(retransmitted.sequence_number, UNACKNOWLEDGED);
+        assert_eq!(queue.retransmission_timeout, Duration::from_secs(2));
+        assert_eq!(queue.expiry, Some(now + Duration::from_secs(2)));
+        assert_eq!(queue.segments[0].transmissions, 2);
+
+        for _ in 0..10 {
+            queue.on_timeout(now);
+        }
+        assert_eq!(queue.retransmission_timeout, MAX_RETRANSMISSION_TIMEOUT);
+        assert_eq!(queue.retries, 11);
+    }
+
+    #[test]
+    fn retransmitted_segments_are_not_sampled() {
+        let mut queue = queue(1);
+        let now = Instant::now();
+        queue.on_timeout(now);
+        queue.acknowledge(
+            UNACKNOWLEDGED + SEGMENT_SIZE,
+            now + Duration::from_millis(100),
+            true,
+        );
+
+        // Karn's algorithm keeps the backed off timeout until a new segment is sampled.
+        assert_eq!(queue.smoothed_round_trip_time, None);
+        assert_eq!(queue.retransmission_timeout, Duration::from_secs(2));
+        assert_eq!(queue.retries, 0);
+        assert_eq!(queue.expiry, None);
+    }
+
+    #[test]
+    fn gives_up_after_max_retransmissions() {
+        let mut queue = queue(1);
+        let now = Instant::now();
+        for _ in 0..MAX_RETRANSMISSIONS - 1 {
+            queue.on_timeout(now);
+        }
+        assert!(!queue.is_exhausted());
+
+        queue.on_timeout(now);
+        assert!(queue.is_exhausted());
+    }
+}
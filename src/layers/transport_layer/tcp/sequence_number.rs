@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, AddAssign, Sub};
+
+// Half of the sequence number space, two sequence numbers this far apart can't be ordered.
+const HALF_SEQUENCE_SPACE: u32 = 1 << 31;
+
+// A sequence number, which wraps around to 0 after 2^32 - 1.
+// Sequence numbers are compared using serial number arithmetic as specified in https://datatracker.ietf.org/doc/html/rfc1982
+// i.e. a sequence number is greater than the 2^31 - 1 sequence numbers that precede it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SequenceNumber(pub u32);
+
+impl SequenceNumber {
+    // Whether the sequence number lies in the window of the given length starting at start,
+    // i.e. start =< self < start + length.
+    pub fn is_within(self, start: SequenceNumber, length: u32) -> bool {
+        self - start < length
+    }
+}
+
+impl Add<u32> for SequenceNumber {
+    type Output = SequenceNumber;
+
+    fn add(self, rhs: u32) -> Self::Output {
+        SequenceNumber(self.0.wrapping_add(rhs))
+    }
+}
+
+impl AddAssign<u32> for SequenceNumber {
+    fn add_assign(&mut self, rhs: u32) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<u32> for SequenceNumber {
+    type Output = SequenceNumber;
+
+    fn sub(self, rhs: u32) -> Self::Output {
+        SequenceNumber(self.0.wrapping_sub(rhs))
+    }
+}
+
+// The distance from rhs forward to self, i.e. the number of sequence numbers from rhs up to (excluding) self.
+impl Sub<SequenceNumber> for SequenceNumber {
+    type Output = u32;
+
+    fn sub(self, rhs: SequenceNumber) -> Self::Output {
+        self.0.wrapping_sub(rhs.0)
+    }
+}
+
+impl PartialOrd for SequenceNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match *self - *other {
+            0 => Some(Ordering::Equal),
+            HALF_SEQUENCE_SPACE => None,
+            distance if distance < HALF_SEQUENCE_SPACE => Some(Ordering::Greater),
+            _ => Some(Ordering::Less),
+        }
+    }
+}
+
+impl Display for SequenceNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addition_wraps_around() {
+        assert_eq!(SequenceNumber(u32::MAX) + 1, SequenceNumber(0));
+        assert_eq!(SequenceNumber(u32::MAX - 1) + 5, SequenceNumber(3));
+        assert_eq!(SequenceNumber(2) - 5, SequenceNumber(u32::MAX - 2));
+        assert_eq!(SequenceNumber(3) - SequenceNumber(u32::MAX - 1), 5);
+    }
+
+    #[test]
+    fn comparison_wraps_around() {
+        let max = SequenceNumber(u32::MAX);
+        assert!(SequenceNumber(0) > max);
+        assert!(SequenceNumber(10) > max);
+        assert!(max < SequenceNumber(10));
+        assert!(max > SequenceNumber(u32::MAX - 10));
+        assert_eq!(
+            max.partial_cmp(&SequenceNumber(u32::MAX)),
+            Some(Ordering::Equal)
+        );
+
+        // The furthest ahead a sequence number can be and still be greater.
+        assert!(max + (HALF_SEQUENCE_SPACE - 1) > max);
+        assert!(max + (HALF_SEQUENCE_SPACE + 1) < max);
+    }
+
+    #[test]
+    fn half_the_sequence_space_apart_is_unordered() {
+        let a = SequenceNumber(u32::MAX);
+        let b = a + HALF_SEQUENCE_SPACE;
+        assert_eq!(a.partial_cmp(&b), None);
+        assert_eq!(b.partial_cmp(&a), None);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn window_wraps_around() {
+        let start = SequenceNumber(u32::MAX - 1);
+        assert!(start.is_within(start, 1));
+        assert!(SequenceNumber(u32::MAX).is_within(start, 4));
+        assert!(SequenceNumber(1).is_within(start, 4));
+        assert!(!SequenceNumber(2).is_within(start, 4));
+        assert!(!SequenceNumber(u32::MAX - 2).is_within(start, 4));
+        assert!(!start.is_within(start, 0));
+    }
+}
@@ -0,0 +1,32 @@
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
+use crate::layers::transport_layer::tcp::tcb::TCB;
+use crate::layers::transport_layer::tcp::tcp::TCP;
+
+/// Handle an incoming TCP segment when the connection is in the CLOSE_WAIT state,
+/// i.e. the peer has closed its side and we are waiting for our application to close ours.
+pub fn handle_close_wait_receive(tcb: &TCB, segment: &TCP) -> eyre::Result<TCPStateChange> {
+    if let Some(state_change) = tcb.check_sequence_number(segment) {
+        return Ok(state_change);
+    }
+
+    if let Some(state_change) = tcb.process_reset_and_syn(segment) {
+        return Ok(state_change);
+    }
+
+    if !segment.control_bits.ack {
+        eyre::bail!("missing ack flag");
+    }
+
+    let new_tcb = tcb.process_acknowledgement(segment);
+
+    // The peer has already sent its FIN so anything in sequence space is a retransmission,
+    // most likely because our ACK of the FIN was lost.
+    if segment.data.is_empty() && !segment.control_bits.fin {
+        return Ok(TCPStateChange::NoResponse(new_tcb));
+    }
+
+    let ack = new_tcb.create_segment(new_tcb.send_sequence.next, ControlBits::get_ack(), vec![]);
+
+    Ok(TCPStateChange::WithResponse(new_tcb, ack))
+}
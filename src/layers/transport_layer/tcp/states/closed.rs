@@ -0,0 +1,42 @@
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::tcp::TCP;
+
+/// Handle an incoming TCP segment for a connection that does not exist, i.e. is in the CLOSED state.
+/// Returns the RST to respond with, if any.
+/// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.7.1
+pub fn handle_closed_receive(segment: &TCP) -> Option<TCP> {
+    // Never respond to a RST with a RST.
+    if segment.control_bits.rst {
+        return None;
+    }
+
+    let (sequence_number, acknowledgement_number, control_bits) = if segment.control_bits.ack {
+        (
+            segment.acknowledgement_number,
+            SequenceNumber::default(),
+            ControlBits::get_rst(),
+        )
+    } else {
+        (
+            SequenceNumber::default(),
+            segment.sequence_number + segment.segment_length(),
+            ControlBits::get_rst_ack(),
+        )
+    };
+
+    Some(TCP {
+        src_port: segment.dst_port,
+        dst_port: segment.src_port,
+        sequence_number,
+        acknowledgement_number,
+        data_offset: TCP::calculate_data_offset(&[]),
+        reserved: 0,
+        control_bits,
+        window: 0,
+        checksum: 0,
+        urgent_pointer: 0,
+        options: vec![],
+        data: vec![],
+    })
+}
@@ -0,0 +1,48 @@
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
+use crate::layers::transport_layer::tcp::states::tcp_state::TcpState;
+use crate::layers::transport_layer::tcp::tcb::{MAXIMUM_SEGMENT_LIFETIME, TCB};
+use crate::layers::transport_layer::tcp::tcp::TCP;
+use std::time::Instant;
+
+/// Handle an incoming TCP segment when the connection is in the CLOSING state,
+/// i.e. both ends have sent a FIN but ours has not yet been acknowledged.
+pub fn handle_closing_receive(tcb: &TCB, segment: &TCP) -> eyre::Result<TCPStateChange> {
+    if let Some(state_change) = tcb.check_sequence_number(segment) {
+        return Ok(state_change);
+    }
+
+    if let Some(state_change) = tcb.process_reset_and_syn(segment) {
+        return Ok(state_change);
+    }
+
+    if !segment.control_bits.ack {
+        eyre::bail!("missing ack flag");
+    }
+
+    let acknowledged = tcb.process_acknowledgement(segment);
+
+    let (state, time_wait_expiry) = if acknowledged.is_fin_acknowledged() {
+        (
+            TcpState::TimeWait,
+            Some(Instant::now() + 2 * MAXIMUM_SEGMENT_LIFETIME),
+        )
+    } else {
+        (TcpState::CLosing, None)
+    };
+
+    let new_tcb = TCB {
+        state,
+        time_wait_expiry,
+        ..acknowledged
+    };
+
+    // A retransmitted FIN means the peer never received our ACK of it.
+    if !segment.control_bits.fin {
+        return Ok(TCPStateChange::NoResponse(new_tcb));
+    }
+
+    let ack = new_tcb.create_segment(new_tcb.send_sequence.next, ControlBits::get_ack(), vec![]);
+
+    Ok(TCPStateChange::WithResponse(new_tcb, ack))
+}
@@ -0,0 +1,51 @@
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
+use crate::layers::transport_layer::tcp::states::tcp_state::TcpState;
+use crate::layers::transport_layer::tcp::tcb::{MAXIMUM_SEGMENT_LIFETIME, TCB};
+use crate::layers::transport_layer::tcp::tcp::TCP;
+use std::time::Instant;
+
+/// Handle an incoming TCP segment when the connection is in the FIN_WAIT_2 state,
+/// i.e. our FIN has been acknowledged and we are waiting for the FIN of the peer.
+pub fn handle_fin_wait_2_receive(tcb: &TCB, segment: &TCP) -> eyre::Result<TCPStateChange> {
+    if let Some(state_change) = tcb.check_sequence_number(segment) {
+        return Ok(state_change);
+    }
+
+    if let Some(state_change) = tcb.process_reset_and_syn(segment) {
+        return Ok(state_change);
+    }
+
+    if !segment.control_bits.ack {
+        eyre::bail!("missing ack flag");
+    }
+
+    let acknowledged = tcb.process_acknowledgement(segment);
+    let mut received = acknowledged.receive_text(segment);
+
+    let (state, time_wait_expiry) = if received.receive_sequence.is_fin_in_sequence(segment) {
+        received.receive_sequence.next += 1; // FIN takes 1 sequence number.
+        (
+            TcpState::TimeWait,
+            Some(Instant::now() + 2 * MAXIMUM_SEGMENT_LIFETIME),
+        )
+    } else {
+        (TcpState::FinWait2, None)
+    };
+
+    let occupies_sequence_space = !segment.data.is_empty() || segment.control_bits.fin;
+
+    let new_tcb = TCB {
+        state,
+        time_wait_expiry,
+        ..received
+    };
+
+    if !occupies_sequence_space {
+        return Ok(TCPStateChange::NoResponse(new_tcb));
+    }
+
+    let ack = new_tcb.create_segment(new_tcb.send_sequence.next, ControlBits::get_ack(), vec![]);
+
+    Ok(TCPStateChange::WithResponse(new_tcb, ack))
+}
@@ -0,0 +1,34 @@
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
+use crate::layers::transport_layer::tcp::tcb::TCB;
+use crate::layers::transport_layer::tcp::tcp::TCP;
+
+/// Handle an incoming TCP segment when the connection is in the LAST_ACK state,
+/// i.e. both ends have closed and we are waiting for the acknowledgement of our FIN.
+pub fn handle_last_ack_receive(tcb: &TCB, segment: &TCP) -> eyre::Result<TCPStateChange> {
+    if let Some(state_change) = tcb.check_sequence_number(segment) {
+        return Ok(state_change);
+    }
+
+    if let Some(state_change) = tcb.process_reset_and_syn(segment) {
+        return Ok(state_change);
+    }
+
+    if !segment.control_bits.ack {
+        eyre::bail!("missing ack flag");
+    }
+
+    let new_tcb = tcb.process_acknowledgement(segment);
+    if new_tcb.is_fin_acknowledged() {
+        return Ok(TCPStateChange::Closed);
+    }
+
+    // A retransmitted FIN means the peer never received our ACK of it.
+    if !segment.control_bits.fin {
+        return Ok(TCPStateChange::NoResponse(new_tcb));
+    }
+
+    let ack = new_tcb.create_segment(new_tcb.send_sequence.next, ControlBits::get_ack(), vec![]);
+
+    Ok(TCPStateChange::WithResponse(new_tcb, ack))
+}
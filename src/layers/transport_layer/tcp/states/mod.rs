@@ -3,3 +3,11 @@ pub mod listen;
 pub mod syn_received;
 pub mod state_change;
 pub mod established;
+pub mod syn_sent;
+pub mod fin_wait_1;
+pub mod fin_wait_2;
+pub mod close_wait;
+pub mod closing;
+pub mod last_ack;
+pub mod time_wait;
+pub mod closed;
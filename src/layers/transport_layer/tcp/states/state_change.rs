@@ -3,5 +3,24 @@ use crate::layers::transport_layer::tcp::tcp::TCP;
 
 pub enum TCPStateChange {
     WithResponse(TCB, TCP),
+    // Multiple segments to send, e.g. when the data we are sending does not fit in a single segment.
+    WithResponses(TCB, Vec<TCP>),
     NoResponse(TCB),
-}
\ No newline at end of file
+    // The connection has reached the CLOSED state and its TCB should be deleted.
+    Closed,
+    // The connection has been reset and its TCB should be deleted, sending the RST if we are the one resetting it.
+    Reset(Option<TCP>),
+    // The peer stopped acknowledging what we send and the connection has been aborted.
+    TimedOut,
+}
+
+impl TCPStateChange {
+    // Creates the state change that keeps the TCB and sends the given segments, if any.
+    pub fn with_responses(tcb: TCB, mut responses: Vec<TCP>) -> TCPStateChange {
+        match responses.len() {
+            0 => TCPStateChange::NoResponse(tcb),
+            1 => TCPStateChange::WithResponse(tcb, responses.remove(0)),
+            _ => TCPStateChange::WithResponses(tcb, responses),
+        }
+    }
+}
@@ -1,30 +1,65 @@
-use crate::layers::transport_layer::tcp::receive_sequence::ReceiveSequence;
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::send_sequence::SendSequence;
+use crate::layers::transport_layer::tcp::states::closed::handle_closed_receive;
+use crate::layers::transport_layer::tcp::states::established::handle_established_receive;
 use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
 use crate::layers::transport_layer::tcp::states::tcp_state::TcpState;
 use crate::layers::transport_layer::tcp::tcb::TCB;
 use crate::layers::transport_layer::tcp::tcp::TCP;
 
+/// Handle an incoming TCP segment when the connection is in the SYN_RECEIVED state,
+/// i.e. we have sent our SYN-ACK and are waiting for it to be acknowledged.
 pub fn handle_syn_received_receive(tcb: &TCB, segment: &TCP) -> eyre::Result<TCPStateChange> {
+    // The peer retransmitted its SYN, most likely because our SYN-ACK was lost.
+    if segment.control_bits.syn
+        && !segment.control_bits.ack
+        && segment.sequence_number == tcb.receive_sequence.initial_receive_sequence
+    {
+        let syn_ack = tcb.create_segment(
+            tcb.send_sequence.initial_send_sequence,
+            ControlBits::get_syn_ack(),
+            vec![],
+        );
+        return Ok(TCPStateChange::WithResponse(tcb.clone(), syn_ack));
+    }
+
+    if let Some(state_change) = tcb.check_sequence_number(segment) {
+        return Ok(state_change);
+    }
+
+    if let Some(state_change) = tcb.process_reset_and_syn(segment) {
+        return Ok(state_change);
+    }
+
     if !segment.control_bits.ack {
         eyre::bail!("missing ack flag");
     }
 
-    // TODO: handle if they sent data with the ack.
+    // The ACK has to acknowledge our SYN (SND.UNA < SEG.ACK =< SND.NXT), otherwise it is reset.
+    let ack = segment.acknowledgement_number;
+    if ack <= tcb.send_sequence.unacknowledged || ack > tcb.send_sequence.next {
+        return Ok(match handle_closed_receive(segment) {
+            Some(reset) => TCPStateChange::WithResponse(tcb.clone(), reset),
+            None => TCPStateChange::NoResponse(tcb.clone()),
+        });
+    }
 
+    let acknowledged = tcb.process_acknowledgement(segment);
     let new_tcb = TCB {
-        local_port: tcb.local_port,
-        remote_port: tcb.remote_port,
-        send_sequence: tcb.send_sequence.clone(),
-        receive_sequence: ReceiveSequence {
-            next: segment.sequence_number, // SYN takes 1 segment number.
-            window: segment.window,
-            urgent_pointer: 0, // TODO: Implement
-            initial_receive_sequence: tcb.receive_sequence.initial_receive_sequence,
+        send_sequence: SendSequence {
+            window: acknowledged.segment_window(segment),
+            last_window_update_sequence: segment.sequence_number,
+            last_window_update_ack: segment.acknowledgement_number,
+            ..acknowledged.send_sequence.clone()
         },
         state: TcpState::Established,
-        send_buffer: tcb.send_buffer.to_owned(),
-        receive_buffer: tcb.receive_buffer.to_owned(),
+        ..acknowledged
     };
 
+    // Any data or FIN sent together with the ACK is handled as in ESTABLISHED.
+    if segment.segment_length() > 0 {
+        return handle_established_receive(&new_tcb, segment);
+    }
+
     Ok(TCPStateChange::NoResponse(new_tcb))
 }
@@ -0,0 +1,130 @@
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::receive_sequence::ReceiveSequence;
+use crate::layers::transport_layer::tcp::send_sequence::SendSequence;
+use crate::layers::transport_layer::tcp::states::closed::handle_closed_receive;
+use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
+use crate::layers::transport_layer::tcp::states::tcp_state::TcpState;
+use crate::layers::transport_layer::tcp::tcb::TCB;
+use crate::layers::transport_layer::tcp::tcp::TCP;
+
+/// Handle an incoming TCP segment when the connection is in the SYN_SENT state,
+/// i.e. we have actively opened the connection and are waiting for the peer's SYN.
+/// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.7.3
+pub fn handle_syn_sent_receive(tcb: &TCB, segment: &TCP) -> eyre::Result<TCPStateChange> {
+    let iss = tcb.send_sequence.initial_send_sequence;
+
+    // The ACK is acceptable if ISS < SEG.ACK =< SND.NXT.
+    let ack_acceptable = segment.acknowledgement_number > iss
+        && segment.acknowledgement_number <= tcb.send_sequence.next;
+
+    if segment.control_bits.ack && !ack_acceptable {
+        // The segment is for some old connection, tell the peer to reset it (unless it is a RST itself).
+        return Ok(match handle_closed_receive(segment) {
+            Some(reset) => TCPStateChange::WithResponse(tcb.clone(), reset),
+            None => TCPStateChange::NoResponse(tcb.clone()),
+        });
+    }
+
+    if segment.control_bits.rst {
+        if segment.control_bits.ack {
+            // The peer refused our connection.
+            return Ok(TCPStateChange::Reset(None));
+        }
+
+        // Without an acceptable ACK the RST might not be for us, drop it.
+        return Ok(TCPStateChange::NoResponse(tcb.clone()));
+    }
+
+    if !segment.control_bits.syn {
+        // Neither SYN nor RST, drop the segment.
+        return Ok(TCPStateChange::NoResponse(tcb.clone()));
+    }
+
+    let negotiated = tcb.negotiate_syn_options(segment);
+
+    let receive_sequence = ReceiveSequence {
+        next: segment.sequence_number + 1, // SYN takes 1 sequence number.
+        window: TCB::receive_buffer_capacity(negotiated.receive_window_shift),
+        urgent_pointer: None,
+        initial_receive_sequence: segment.sequence_number,
+    };
+
+    if segment.control_bits.ack {
+        // Our SYN has been acknowledged, the connection is established.
+        let mut receive_sequence = receive_sequence;
+        receive_sequence.next += segment.data.len() as u32;
+        // The right edge of the window stays where it is.
+        receive_sequence.window = receive_sequence
+            .window
+            .saturating_sub(segment.data.len() as u32);
+
+        let mut receive_buffer = tcb.receive_buffer.to_owned();
+        receive_buffer.extend_from_slice(segment.data.as_slice());
+
+        let new_tcb = TCB {
+            send_sequence: SendSequence {
+                unacknowledged: segment.acknowledgement_number,
+                window: tcb.segment_window(segment),
+                last_window_update_sequence: segment.sequence_number,
+                last_window_update_ack: segment.acknowledgement_number,
+                ..tcb.send_sequence.clone()
+            },
+            receive_sequence,
+            state: TcpState::Established,
+            syn_acknowledged: true,
+            receive_buffer,
+            ..negotiated
+        };
+
+        let options = new_tcb.segment_options();
+
+        let ack = TCP {
+            src_port: new_tcb.local_port,
+            dst_port: new_tcb.remote_port,
+            sequence_number: new_tcb.send_sequence.next,
+            acknowledgement_number: new_tcb.receive_sequence.next,
+            data_offset: TCP::calculate_data_offset(&options),
+            reserved: 0,
+            control_bits: ControlBits::get_ack(),
+            window: new_tcb.advertised_window(false),
+            checksum: 0,
+            urgent_pointer: 0,
+            options,
+            data: vec![],
+        };
+
+        return Ok(TCPStateChange::WithResponse(new_tcb, ack));
+    }
+
+    // Simultaneous open, both ends sent a SYN at the same time.
+    // Acknowledge theirs and resend ours, then wait for the ACK of our SYN in SYN_RECEIVED.
+    let new_tcb = TCB {
+        send_sequence: SendSequence {
+            window: tcb.segment_window(segment),
+            last_window_update_sequence: segment.sequence_number,
+            ..tcb.send_sequence.clone()
+        },
+        receive_sequence,
+        state: TcpState::SynReceived,
+        ..negotiated
+    };
+
+    let options = new_tcb.syn_options();
+
+    let syn_ack = TCP {
+        src_port: new_tcb.local_port,
+        dst_port: new_tcb.remote_port,
+        sequence_number: iss,
+        acknowledgement_number: new_tcb.receive_sequence.next,
+        data_offset: TCP::calculate_data_offset(&options),
+        reserved: 0,
+        control_bits: ControlBits::get_syn_ack(),
+        window: new_tcb.advertised_window(true),
+        checksum: 0,
+        urgent_pointer: 0,
+        options,
+        data: vec![],
+    };
+
+    Ok(TCPStateChange::WithResponse(new_tcb, syn_ack))
+}
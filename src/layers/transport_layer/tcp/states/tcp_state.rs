@@ -2,6 +2,7 @@ use std::fmt::{Display, Formatter};
 use std::fmt;
 
 // Closed is not represented as it represents the case where there is no state.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TcpState {
     Listen,
     SynSent,
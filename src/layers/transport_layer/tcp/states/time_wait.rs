@@ -0,0 +1,49 @@
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
+use crate::layers::transport_layer::tcp::tcb::{MAXIMUM_SEGMENT_LIFETIME, TCB};
+use crate::layers::transport_layer::tcp::tcp::TCP;
+use std::time::Instant;
+
+/// Handle an incoming TCP segment when the connection is in the TIME_WAIT state,
+/// i.e. the connection is closed but lingers to handle any retransmission of the peer's FIN.
+pub fn handle_time_wait_receive(tcb: &TCB, segment: &TCP) -> eyre::Result<TCPStateChange> {
+    // A retransmission of the peer's FIN lies before RCV.NXT, so it is unacceptable and acknowledged again,
+    // restarting the 2 MSL timeout.
+    if let Some(state_change) = tcb.check_sequence_number(segment) {
+        let retransmitted_fin = segment.control_bits.fin
+            && segment.sequence_number + segment.segment_length() == tcb.receive_sequence.next;
+
+        return Ok(match state_change {
+            TCPStateChange::WithResponse(new_tcb, ack) if retransmitted_fin => {
+                let new_tcb = TCB {
+                    time_wait_expiry: Some(Instant::now() + 2 * MAXIMUM_SEGMENT_LIFETIME),
+                    ..new_tcb
+                };
+                TCPStateChange::WithResponse(new_tcb, ack)
+            }
+            state_change => state_change,
+        });
+    }
+
+    if let Some(state_change) = tcb.process_reset_and_syn(segment) {
+        return Ok(state_change);
+    }
+
+    if !segment.control_bits.ack {
+        eyre::bail!("missing ack flag");
+    }
+
+    if !segment.control_bits.fin {
+        return Ok(TCPStateChange::NoResponse(tcb.clone()));
+    }
+
+    // The peer retransmitted its FIN, acknowledge it again and restart the 2 MSL timeout.
+    let new_tcb = TCB {
+        time_wait_expiry: Some(Instant::now() + 2 * MAXIMUM_SEGMENT_LIFETIME),
+        ..tcb.clone()
+    };
+
+    let ack = new_tcb.create_segment(new_tcb.send_sequence.next, ControlBits::get_ack(), vec![]);
+
+    Ok(TCPStateChange::WithResponse(new_tcb, ack))
+}
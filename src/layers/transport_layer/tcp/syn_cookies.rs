@@ -0,0 +1,229 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::tcp_ip_port_quad::TCPQuad;
+
+// How often the counter in the cookies advances, and how many periods an older cookie is still accepted for.
+const COUNTER_PERIOD: Duration = Duration::from_secs(64);
+const MAX_COOKIE_AGE: u32 = 1;
+// How long after it was created a cookie may still be accepted at most.
+pub const COOKIE_LIFETIME: Duration =
+    Duration::from_secs(COUNTER_PERIOD.as_secs() * (MAX_COOKIE_AGE as u64 + 1));
+
+// The layout of a cookie, a 5-bit counter followed by the 3-bit index of the MSS and 24 bits of the hash.
+const COUNTER_SHIFT: u32 = 27;
+const COUNTER_MASK: u32 = 0b11111;
+const MAXIMUM_SEGMENT_SIZE_SHIFT: u32 = 24;
+const MAXIMUM_SEGMENT_SIZE_MASK: u32 = 0b111;
+const HASH_MASK: u32 = 0x00FF_FFFF;
+
+// The maximum segment sizes a cookie can encode, the peer's is rounded down to one of these.
+const MAXIMUM_SEGMENT_SIZES: [u16; 8] = [216, 536, 1200, 1360, 1400, 1440, 1460, 8960];
+
+// SYN cookies, which answer a SYN without keeping any state by encoding the connection in our ISS.
+// The ACK of the SYN-ACK returns the cookie, from which the connection is reconstructed if it is valid.
+// As specified in https://datatracker.ietf.org/doc/html/rfc4987#section-3.6
+pub struct SynCookies {
+    // The secret the cookies are hashed with, so the peer can't forge them.
+    secret: RandomState,
+    start: Instant,
+}
+
+impl Default for SynCookies {
+    fn default() -> Self {
+        SynCookies::new()
+    }
+}
+
+impl SynCookies {
+    pub fn new() -> Self {
+        SynCookies {
+            secret: RandomState::new(),
+            start: Instant::now(),
+        }
+    }
+
+    // Creates the cookie for a SYN from the quad with the given ISN, returning it together with the MSS it encodes,
+    // which is the largest one that can be encoded but does not exceed the given one.
+    pub fn encode(
+        &self,
+        quad: &TCPQuad,
+        peer_initial_sequence: SequenceNumber,
+        maximum_segment_size: u16,
+        now: Instant,
+    ) -> (SequenceNumber, u16) {
+        let index = MAXIMUM_SEGMENT_SIZES
+            .iter()
+            .rposition(|&size| size <= maximum_segment_size)
+            .unwrap_or(0) as u32;
+        let counter = self.counter(now);
+
+        let cookie = ((counter & COUNTER_MASK) << COUNTER_SHIFT)
+            | (index << MAXIMUM_SEGMENT_SIZE_SHIFT)
+            | self.hash(quad, peer_initial_sequence, counter, index);
+
+        (
+            SequenceNumber(cookie),
+            MAXIMUM_SEGMENT_SIZES[index as usize],
+        )
+    }
+
+    // Checks the cookie returned by the peer, returning the MSS it encodes if we created it recently.
+    pub fn decode(
+        &self,
+        quad: &TCPQuad,
+        peer_initial_sequence: SequenceNumber,
+        cookie: SequenceNumber,
+        now: Instant,
+    ) -> Option<u16> {
+        let counter = self.counter(now);
+        let age = counter.wrapping_sub(cookie.0 >> COUNTER_SHIFT) & COUNTER_MASK;
+        if age > MAX_COOKIE_AGE || age > counter {
+            return None;
+        }
+
+        let index = (cookie.0 >> MAXIMUM_SEGMENT_SIZE_SHIFT) & MAXIMUM_SEGMENT_SIZE_MASK;
+        let hash = self.hash(quad, peer_initial_sequence, counter - age, index);
+        if cookie.0 & HASH_MASK != hash {
+            return None;
+        }
+
+        Some(MAXIMUM_SEGMENT_SIZES[index as usize])
+    }
+
+    // The number of counter periods since we started creating cookies.
+    fn counter(&self, now: Instant) -> u32 {
+        (now.duration_since(self.start).as_secs() / COUNTER_PERIOD.as_secs()) as u32
+    }
+
+    fn hash(
+        &self,
+        quad: &TCPQuad,
+        peer_initial_sequence: SequenceNumber,
+        counter: u32,
+        index: u32,
+    ) -> u32 {
+        let mut hasher = self.secret.build_hasher();
+        quad.hash(&mut hasher);
+        peer_initial_sequence.hash(&mut hasher);
+        counter.hash(&mut hasher);
+        index.hash(&mut hasher);
+
+        hasher.finish() as u32 & HASH_MASK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::ip_layer::ipv4::ipv4_address::IPAddressV4;
+
+    const PEER_INITIAL_SEQUENCE: SequenceNumber = SequenceNumber(123_456);
+
+    fn quad() -> TCPQuad {
+        TCPQuad {
+            src_ip: IPAddressV4(0x0a000001).into(),
+            dst_ip: IPAddressV4(0x0a000002).into(),
+            src_port: 50000,
+            dst_port: 80,
+        }
+    }
+
+    #[test]
+    fn round_trip_encodes_the_maximum_segment_size() {
+        let cookies = SynCookies::new();
+        let now = cookies.start + 3 * COUNTER_PERIOD;
+
+        for (announced, encoded) in [
+            (100, 216),
+            (536, 536),
+            (1459, 1440),
+            (1460, 1460),
+            (9000, 8960),
+        ] {
+            let (cookie, maximum_segment_size) =
+                cookies.encode(&quad(), PEER_INITIAL_SEQUENCE, announced, now);
+            assert_eq!(maximum_segment_size, encoded);
+            assert_eq!(cookie.0 >> COUNTER_SHIFT, 3);
+            assert_eq!(
+                cookies.decode(&quad(), PEER_INITIAL_SEQUENCE, cookie, now),
+                Some(encoded)
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_cookies_of_the_previous_period() {
+        let cookies = SynCookies::new();
+        let sent = cookies.start + 5 * COUNTER_PERIOD;
+        let (cookie, _) = cookies.encode(&quad(), PEER_INITIAL_SEQUENCE, 1460, sent);
+
+        let next_period = sent + COUNTER_PERIOD;
+        assert_eq!(
+            cookies.decode(&quad(), PEER_INITIAL_SEQUENCE, cookie, next_period),
+            Some(1460)
+        );
+    }
+
+    #[test]
+    fn rejects_stale_cookies() {
+        let cookies = SynCookies::new();
+        let sent = cookies.start + 5 * COUNTER_PERIOD;
+        let (cookie, _) = cookies.encode(&quad(), PEER_INITIAL_SEQUENCE, 1460, sent);
+
+        let stale = sent + (MAX_COOKIE_AGE + 1) * COUNTER_PERIOD;
+        assert_eq!(
+            cookies.decode(&quad(), PEER_INITIAL_SEQUENCE, cookie, stale),
+            None
+        );
+
+        // Nor is a cookie from the future accepted.
+        let before = cookies.start + 4 * COUNTER_PERIOD;
+        assert_eq!(
+            cookies.decode(&quad(), PEER_INITIAL_SEQUENCE, cookie, before),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_cookies() {
+        let cookies = SynCookies::new();
+        let now = cookies.start + COUNTER_PERIOD;
+        let (cookie, _) = cookies.encode(&quad(), PEER_INITIAL_SEQUENCE, 536, now);
+
+        // Claiming a larger MSS, flipping a bit of the hash, or using the cookie for another connection.
+        let larger = SequenceNumber(cookie.0 | (0b111 << MAXIMUM_SEGMENT_SIZE_SHIFT));
+        let flipped = SequenceNumber(cookie.0 ^ 1);
+        let mut other_quad = quad();
+        other_quad.src_port += 1;
+
+        assert_eq!(
+            cookies.decode(&quad(), PEER_INITIAL_SEQUENCE, larger, now),
+            None
+        );
+        assert_eq!(
+            cookies.decode(&quad(), PEER_INITIAL_SEQUENCE, flipped, now),
+            None
+        );
+        assert_eq!(
+            cookies.decode(&other_quad, PEER_INITIAL_SEQUENCE, cookie, now),
+            None
+        );
+        assert_eq!(
+            cookies.decode(&quad(), PEER_INITIAL_SEQUENCE + 1, cookie, now),
+            None
+        );
+
+        // Cookies of another stack instance are hashed with another secret.
+        let other_secret = SynCookies {
+            secret: RandomState::new(),
+            start: cookies.start,
+        };
+        assert_eq!(
+            other_secret.decode(&quad(), PEER_INITIAL_SEQUENCE, cookie, now),
+            None
+        );
+    }
+}
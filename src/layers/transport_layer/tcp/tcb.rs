@@ -1,32 +1,1486 @@
-use crate::layers::transport_layer::tcp::receive_sequence::ReceiveSequence;
+use crate::layers::transport_layer::tcp::congestion_control::{
+    CongestionControl, CongestionControlAlgorithm,
+};
+use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::keepalive::Keepalive;
+use crate::layers::transport_layer::tcp::out_of_order_queue::OutOfOrderQueue;
+use crate::layers::transport_layer::tcp::receive_sequence::{
+    ReceiveSequence, RECEIVE_BUFFER_CAPACITY, RECEIVE_WINDOW_SHIFT,
+};
+use crate::layers::transport_layer::tcp::retransmission_queue::{
+    RetransmissionQueue, DUPLICATE_THRESHOLD,
+};
 use crate::layers::transport_layer::tcp::send_sequence::SendSequence;
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::states::close_wait::handle_close_wait_receive;
+use crate::layers::transport_layer::tcp::states::closing::handle_closing_receive;
 use crate::layers::transport_layer::tcp::states::established::handle_established_receive;
+use crate::layers::transport_layer::tcp::states::fin_wait_1::handle_fin_wait_1_receive;
+use crate::layers::transport_layer::tcp::states::fin_wait_2::handle_fin_wait_2_receive;
+use crate::layers::transport_layer::tcp::states::last_ack::handle_last_ack_receive;
 use crate::layers::transport_layer::tcp::states::listen::handle_listen_receive;
 use crate::layers::transport_layer::tcp::states::state_change::TCPStateChange;
 use crate::layers::transport_layer::tcp::states::syn_received::handle_syn_received_receive;
+use crate::layers::transport_layer::tcp::states::syn_sent::handle_syn_sent_receive;
 use crate::layers::transport_layer::tcp::states::tcp_state::TcpState;
+use crate::layers::transport_layer::tcp::states::time_wait::handle_time_wait_receive;
 use crate::layers::transport_layer::tcp::tcp::TCP;
+use crate::layers::transport_layer::tcp::tcp_ip_port_quad::TCPQuad;
+use crate::layers::transport_layer::tcp::tcp_option::{SackBlock, TcpOption};
+use crate::layers::transport_layer::tcp::tcp_timer::TcpTimer;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// The Maximum Segment Lifetime, as specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.4.2
+pub const MAXIMUM_SEGMENT_LIFETIME: Duration = Duration::from_secs(2 * 60);
+
+// The largest segment we may send when the peer has not told us otherwise,
+// as specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.7.1
+pub const DEFAULT_MAXIMUM_SEGMENT_SIZE: u16 = 536;
+
+// How long we may wait for more data before acknowledging what was received,
+// as specified in https://datatracker.ietf.org/doc/html/rfc1122#section-4.2.3.2
+const DELAYED_ACKNOWLEDGEMENT_TIMEOUT: Duration = Duration::from_millis(200);
+
+// The longest we wait between two window probes,
+// as specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.8.6.1
+const MAX_PERSIST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// The largest shift count a window may be scaled by,
+// as specified in https://datatracker.ietf.org/doc/html/rfc7323#section-2.3
+const MAX_WINDOW_SHIFT: u8 = 14;
+
+// The most SACK blocks that fit in the option space of a segment, which is less if it also carries timestamps,
+// as specified in https://datatracker.ietf.org/doc/html/rfc2018#section-3
+const MAX_SACK_BLOCKS: usize = 4;
+const MAX_SACK_BLOCKS_WITH_TIMESTAMPS: usize = 3;
+
+// How long TS.Recent remains valid without being updated, after which PAWS no longer applies,
+// as specified in https://datatracker.ietf.org/doc/html/rfc7323#section-5.5
+const TIMESTAMP_RECENT_LIFETIME: Duration = Duration::from_secs(24 * 24 * 60 * 60);
 
 // As specified in https://datatracker.ietf.org/doc/html/rfc793#section-3.2
+#[derive(Clone)]
 pub struct TCB {
     pub local_port: u16,  // Socket number?
     pub remote_port: u16, // Socket number?
     // TODO: Should contain ''The security and precedence of the connection''
-    // TODO: Should contain pointer to retransmit queue.
     pub send_sequence: SendSequence,
     pub receive_sequence: ReceiveSequence,
     pub state: TcpState,
+    // Whether the peer has acknowledged our SYN, which is the first thing any acknowledgement covers.
+    // Comparing SND.UNA to the ISS cannot tell, as SND.UNA may wrap around back onto it.
+    pub syn_acknowledged: bool,
+    // The sequence number of our FIN once we have sent it, which is only ever sent once.
+    pub fin_sequence: Option<SequenceNumber>,
+    // The data we have not yet sent or the peer has not yet acknowledged, starting at SND.UNA.
     pub send_buffer: Vec<u8>,
     pub receive_buffer: Vec<u8>,
+    // Received data that is not yet next in sequence.
+    pub out_of_order_queue: OutOfOrderQueue,
+    // The last byte of urgent data the peer sent, which is taken out of the stream to be read out of band,
+    // and how many bytes of the receive buffer precede the point it was taken from (the urgent mark).
+    pub urgent_data: Option<u8>,
+    pub urgent_mark: Option<usize>,
+    // The largest amount of data we may send in a single segment, as negotiated during the handshake.
+    pub send_maximum_segment_size: u16,
+    // The largest amount of data the peer may send us in a single segment, as we advertise in our SYN.
+    pub receive_maximum_segment_size: u16,
+    // The shift counts the windows of the peer (Snd.Wind.Shift) and ours (Rcv.Wind.Shift) are scaled by,
+    // both are 0 if the peer does not support window scaling.
+    pub send_window_shift: u8,
+    pub receive_window_shift: u8,
+    // Whether both ends selectively acknowledge data, which we offer in every SYN.
+    pub sack_permitted: bool,
+    // Whether both ends send timestamps, which we offer in every SYN.
+    pub timestamps_enabled: bool,
+    // The latest timestamp of the peer that we echo (TS.Recent) and when we recorded it.
+    pub timestamp_recent: u32,
+    pub timestamp_recent_time: Option<Instant>,
+    // When the connection may be deleted after lingering in TIME_WAIT for 2 * MSL.
+    pub time_wait_expiry: Option<Instant>,
+    // The amount of data received in sequence since we last sent an acknowledgement,
+    // and when we acknowledge it at the latest.
+    pub unacknowledged_received: u32,
+    pub delayed_acknowledgement_expiry: Option<Instant>,
+    // When we next probe the peer's zero window, and how many probes it has not opened the window for.
+    pub persist_expiry: Option<Instant>,
+    pub window_probes: u32,
+    // How we check whether the peer of an idle connection is still there, None if we do not.
+    pub keepalive: Option<Keepalive>,
+    // When we next send a keepalive probe, and how many probes have gone unanswered.
+    pub keepalive_expiry: Option<Instant>,
+    pub keepalive_probes: u32,
+    // When we last heard from the peer, the connection is idle from then on.
+    // The keepalive timer is not moved on every segment, it checks this once it expires instead.
+    pub last_activity: Option<Instant>,
+    pub retransmission_queue: RetransmissionQueue,
+    // Whether small segments are sent right away rather than coalesced by Nagle's algorithm.
+    pub no_delay: bool,
+    // Decides how much data we may have in flight, on top of what the peer's window allows.
+    pub congestion_control: Box<dyn CongestionControl>,
+    // The number of duplicate acknowledgements received in a row.
+    pub duplicate_acknowledgements: u32,
+    // Whether we are recovering from a loss, until everything sent before it was detected has been acknowledged.
+    pub fast_recovery: bool,
+    // SND.NXT when we last detected a loss (recover), duplicate acknowledgements below it signal no new loss.
+    pub recover: Option<SequenceNumber>,
 }
 
 impl TCB {
-    pub fn on_packet_received(&self, tcp: &TCP) -> eyre::Result<TCPStateChange> {
+    /// Actively opens a connection to the remote end of the quad (OPEN call, active mode).
+    /// Returns the new TCB in the SYN_SENT state together with the initial SYN to send.
+    pub fn connect(
+        quad: &TCPQuad,
+        initial_send_sequence: SequenceNumber,
+        receive_maximum_segment_size: u16,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> TCPStateChange {
+        // WL1 & WL2 are not known until we receive the SYN of the peer.
+        let mut send_sequence =
+            SendSequence::new_send_sequence(initial_send_sequence, SequenceNumber::default());
+        let sequence_number = send_sequence.next;
+
+        let new_tcb = TCB {
+            local_port: quad.dst_port,
+            remote_port: quad.src_port,
+            send_sequence: {
+                send_sequence.next += 1; // SYN takes 1 sequence number.
+                send_sequence
+            },
+            receive_sequence: ReceiveSequence {
+                window: TCB::receive_buffer_capacity(RECEIVE_WINDOW_SHIFT),
+                ..ReceiveSequence::default()
+            },
+            state: TcpState::SynSent,
+            syn_acknowledged: false,
+            fin_sequence: None,
+            send_buffer: vec![],
+            receive_buffer: vec![],
+            out_of_order_queue: OutOfOrderQueue::default(),
+            urgent_data: None,
+            urgent_mark: None,
+            send_maximum_segment_size: DEFAULT_MAXIMUM_SEGMENT_SIZE,
+            receive_maximum_segment_size,
+            send_window_shift: 0,
+            receive_window_shift: RECEIVE_WINDOW_SHIFT,
+            sack_permitted: true,
+            timestamps_enabled: true,
+            timestamp_recent: 0,
+            timestamp_recent_time: None,
+            time_wait_expiry: None,
+            unacknowledged_received: 0,
+            delayed_acknowledgement_expiry: None,
+            persist_expiry: None,
+            window_probes: 0,
+            keepalive: None,
+            keepalive_expiry: None,
+            keepalive_probes: 0,
+            last_activity: None,
+            retransmission_queue: RetransmissionQueue::default(),
+            no_delay: false,
+            congestion_control: congestion_control.create(DEFAULT_MAXIMUM_SEGMENT_SIZE),
+            duplicate_acknowledgements: 0,
+            fast_recovery: false,
+            recover: None,
+        };
+
+        let options = new_tcb.syn_options();
+
+        let syn = TCP {
+            src_port: new_tcb.local_port,
+            dst_port: new_tcb.remote_port,
+            sequence_number,
+            acknowledgement_number: SequenceNumber::default(),
+            data_offset: TCP::calculate_data_offset(&options),
+            reserved: 0,
+            control_bits: ControlBits::get_syn(),
+            window: new_tcb.advertised_window(true),
+            checksum: 0,
+            urgent_pointer: 0,
+            options,
+            data: vec![],
+        };
+
+        TCB::track_retransmissions(TCPStateChange::WithResponse(new_tcb, syn))
+    }
+
+    /// Reconstructs a connection in the SYN_RECEIVED state from the SYN cookie we sent as our ISS,
+    /// which only remembers the MSS of the peer, so neither window scaling, SACK nor timestamps are used.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc4987#section-3.6
+    pub fn from_syn_cookie(
+        quad: &TCPQuad,
+        cookie: SequenceNumber,
+        peer_initial_sequence: SequenceNumber,
+        send_maximum_segment_size: u16,
+        receive_maximum_segment_size: u16,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> TCB {
+        TCB {
+            local_port: quad.dst_port,
+            remote_port: quad.src_port,
+            send_sequence: SendSequence {
+                unacknowledged: cookie,
+                next: cookie + 1, // SYN takes 1 sequence number.
+                window: 0,
+                urgent_pointer: None,
+                last_window_update_sequence: peer_initial_sequence,
+                last_window_update_ack: cookie,
+                initial_send_sequence: cookie,
+            },
+            receive_sequence: ReceiveSequence {
+                next: peer_initial_sequence + 1, // SYN takes 1 sequence number.
+                window: TCB::receive_buffer_capacity(0),
+                urgent_pointer: None,
+                initial_receive_sequence: peer_initial_sequence,
+            },
+            state: TcpState::SynReceived,
+            send_maximum_segment_size,
+            receive_maximum_segment_size,
+            send_window_shift: 0,
+            receive_window_shift: 0,
+            sack_permitted: false,
+            timestamps_enabled: false,
+            congestion_control: congestion_control.create(send_maximum_segment_size),
+            ..TCB::default()
+        }
+    }
+
+    /// Queues data to be sent to the peer (SEND call), sending as much of it as the peer's window allows.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.2
+    pub fn write(&self, data: &[u8]) -> eyre::Result<TCPStateChange> {
         match &self.state {
+            // The data is sent once the connection is established.
+            TcpState::SynSent
+            | TcpState::SynReceived
+            | TcpState::Established
+            | TcpState::CloseWait => {}
+            TcpState::Listen => eyre::bail!("connection not yet opened"),
+            state => eyre::bail!("connection closing, cannot write in state {state}"),
+        }
+
+        let mut new_tcb = self.clone();
+        new_tcb.send_buffer.extend_from_slice(data);
+
+        Ok(TCB::transmit(TCPStateChange::NoResponse(new_tcb)))
+    }
+
+    /// Queues data to be sent to the peer as urgent data (SEND call with the URGENT flag),
+    /// moving the urgent pointer (SND.UP) past it so every segment sent up to there points the peer to its end.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.8.5
+    pub fn write_urgent(&self, data: &[u8]) -> eyre::Result<TCPStateChange> {
+        if data.is_empty() {
+            eyre::bail!("no urgent data to send");
+        }
+
+        let mut new_tcb = self.clone();
+        let end = new_tcb.send_buffer_sequence() + (new_tcb.send_buffer.len() + data.len()) as u32;
+        new_tcb.send_sequence.urgent_pointer = Some(end);
+
+        new_tcb.write(data)
+    }
+
+    /// Closes our sending side of the connection (CLOSE call).
+    /// The FIN is sent once all data written before has been sent.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.4
+    pub fn close(&self) -> eyre::Result<TCPStateChange> {
+        let next_state = match &self.state {
+            TcpState::Listen | TcpState::SynSent => return Ok(TCPStateChange::Closed),
+            TcpState::SynReceived | TcpState::Established => TcpState::FinWait1,
+            TcpState::CloseWait => TcpState::LastAck,
+            state => eyre::bail!("connection closing, cannot close in state {state}"),
+        };
+
+        let new_tcb = TCB {
+            state: next_state,
+            ..self.clone()
+        };
+
+        Ok(TCB::transmit(TCPStateChange::NoResponse(new_tcb)))
+    }
+
+    /// Takes up to length bytes of the data the peer has sent out of the receive buffer (RECEIVE call).
+    /// Reading stops at the urgent mark, so the application can tell the data sent before the urgent data apart.
+    /// If this opens the receive window far enough the peer learns about it with a window update.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.3
+    pub fn read(&self, length: usize) -> eyre::Result<(Vec<u8>, TCPStateChange)> {
+        if self.state == TcpState::Listen {
+            eyre::bail!("connection not yet opened");
+        }
+
+        let mut new_tcb = self.clone();
+        let mut length = length.min(new_tcb.receive_buffer.len());
+        new_tcb.urgent_mark = match new_tcb.urgent_mark {
+            Some(mark) if mark > 0 => {
+                length = length.min(mark);
+                Some(mark - length)
+            }
+            // Reading on from the mark leaves it behind.
+            Some(_) if length > 0 => None,
+            mark => mark,
+        };
+        let data: Vec<u8> = new_tcb.receive_buffer.drain(..length).collect();
+
+        let window =
+            new_tcb.receive_window(new_tcb.receive_sequence.next, new_tcb.receive_buffer.len());
+        let window_opened = window > new_tcb.receive_sequence.window;
+        new_tcb.receive_sequence.window = window;
+
+        // Only a peer that has not closed its side yet cares about our window.
+        let peer_sending = matches!(
+            new_tcb.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        );
+        let state_change = if window_opened && peer_sending {
+            let update =
+                new_tcb.create_segment(new_tcb.send_sequence.next, ControlBits::get_ack(), vec![]);
+            TCPStateChange::WithResponse(new_tcb, update)
+        } else {
+            TCPStateChange::NoResponse(new_tcb)
+        };
+
+        Ok((data, TCB::transmit(state_change)))
+    }
+
+    /// Takes the byte of urgent data the peer has sent out of the stream, None if there is none.
+    /// Like BSD sockets only the last byte of the urgent data is read out of band, telnet's interrupts need no more.
+    pub fn read_urgent(&self) -> eyre::Result<(Option<u8>, TCPStateChange)> {
+        if self.state == TcpState::Listen {
+            eyre::bail!("connection not yet opened");
+        }
+
+        let new_tcb = TCB {
+            urgent_data: None,
+            ..self.clone()
+        };
+
+        Ok((self.urgent_data, TCPStateChange::NoResponse(new_tcb)))
+    }
+
+    /// Whether the peer has announced urgent data that has not been read yet, i.e. the application should
+    /// read on to the urgent mark, as specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.7.4
+    pub fn is_urgent_pending(&self) -> bool {
+        let announced = self
+            .receive_sequence
+            .urgent_pointer
+            .is_some_and(|end| end > self.receive_sequence.next);
+
+        announced || self.urgent_data.is_some()
+    }
+
+    /// Whether the next byte to read is the first one following the urgent data (SIOCATMARK).
+    pub fn is_at_urgent_mark(&self) -> bool {
+        self.urgent_mark == Some(0)
+    }
+
+    /// Enables keepalive for the connection with the given settings, or disables it with None.
+    pub fn set_keepalive(&self, keepalive: Option<Keepalive>) -> TCPStateChange {
+        TCB::restart_keepalive_timer(
+            TCPStateChange::NoResponse(TCB {
+                keepalive,
+                keepalive_expiry: None,
+                ..self.clone()
+            }),
+            true,
+        )
+    }
+
+    /// Disables or re-enables Nagle's algorithm for the connection (TCP_NODELAY),
+    /// sending whatever it held back once it is disabled.
+    pub fn set_no_delay(&self, no_delay: bool) -> TCPStateChange {
+        let new_tcb = TCB {
+            no_delay,
+            ..self.clone()
+        };
+
+        TCB::transmit(TCPStateChange::NoResponse(new_tcb))
+    }
+
+    /// Switches the connection to the given congestion control algorithm, which starts over from its initial window.
+    /// Switching to the algorithm the connection already uses leaves its state alone.
+    pub fn set_congestion_control(&self, algorithm: CongestionControlAlgorithm) -> TCPStateChange {
+        let mut new_tcb = self.clone();
+        if new_tcb.congestion_control.algorithm() != algorithm {
+            new_tcb.congestion_control = algorithm.create(new_tcb.send_maximum_segment_size);
+        }
+
+        TCB::transmit(TCPStateChange::NoResponse(new_tcb))
+    }
+
+    pub fn on_packet_received(&self, tcp: &TCP) -> eyre::Result<TCPStateChange> {
+        let state_change = match &self.state {
             TcpState::Listen => handle_listen_receive(self, tcp),
+            TcpState::SynSent => handle_syn_sent_receive(self, tcp),
             TcpState::SynReceived => handle_syn_received_receive(self, tcp),
             TcpState::Established => handle_established_receive(self, tcp),
-            state => eyre::bail!("unsupported TCP state {{{state}}}"),
+            TcpState::FinWait1 => handle_fin_wait_1_receive(self, tcp),
+            TcpState::FinWait2 => handle_fin_wait_2_receive(self, tcp),
+            TcpState::CloseWait => handle_close_wait_receive(self, tcp),
+            TcpState::CLosing => handle_closing_receive(self, tcp),
+            TcpState::LastAck => handle_last_ack_receive(self, tcp),
+            TcpState::TimeWait => handle_time_wait_receive(self, tcp),
+        }?;
+
+        let state_change = self.control_congestion(state_change, tcp);
+        let state_change = self.delay_acknowledgement(state_change, tcp);
+        let state_change =
+            TCB::restart_keepalive_timer(state_change, self.check_sequence_number(tcp).is_none());
+
+        Ok(TCB::transmit(state_change))
+    }
+
+    /// When the given timer expires, None if it is not running.
+    pub fn timer_expiry(&self, timer: TcpTimer) -> Option<Instant> {
+        match timer {
+            TcpTimer::Retransmission => self.retransmission_queue.expiry,
+            TcpTimer::TimeWait => match self.state {
+                TcpState::TimeWait => self.time_wait_expiry,
+                _ => None,
+            },
+            TcpTimer::DelayedAcknowledgement => self.delayed_acknowledgement_expiry,
+            TcpTimer::Persist => self.persist_expiry,
+            TcpTimer::Keepalive => self.keepalive_expiry,
+        }
+    }
+
+    pub fn on_timer_expired(&self, timer: TcpTimer) -> TCPStateChange {
+        match timer {
+            TcpTimer::Retransmission => self.on_retransmission_timeout(),
+            // The connection has lingered in TIME_WAIT for long enough and is finally closed.
+            TcpTimer::TimeWait => TCPStateChange::Closed,
+            TcpTimer::DelayedAcknowledgement => self.on_delayed_acknowledgement_timeout(),
+            TcpTimer::Persist => self.on_persist_timeout(),
+            TcpTimer::Keepalive => self.on_keepalive_timeout(),
+        }
+    }
+
+    /// Probes the peer of an idle connection with a segment carrying an old sequence number,
+    /// which the peer has to answer with an ACK, giving up on the connection once too many probes go unanswered.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc1122#section-4.2.3.6
+    fn on_keepalive_timeout(&self) -> TCPStateChange {
+        let keepalive = match self.keepalive {
+            Some(keepalive) => keepalive,
+            None => return TCPStateChange::NoResponse(self.clone()),
+        };
+
+        let mut new_tcb = self.clone();
+        let now = Instant::now();
+
+        // The connection is not idle, the retransmission timer notices if the peer is gone.
+        if !new_tcb.retransmission_queue.segments.is_empty() {
+            new_tcb.keepalive_expiry = Some(now + keepalive.idle);
+            return TCPStateChange::NoResponse(new_tcb);
+        }
+
+        // We have heard from the peer since the timer was started, wait until it has been idle for long enough.
+        let idle_until = new_tcb
+            .last_activity
+            .map(|activity| activity + keepalive.idle);
+        if let Some(idle_until) = idle_until.filter(|idle_until| *idle_until > now) {
+            new_tcb.keepalive_expiry = Some(idle_until);
+            return TCPStateChange::NoResponse(new_tcb);
+        }
+
+        if new_tcb.keepalive_probes >= keepalive.probes {
+            return TCPStateChange::TimedOut;
+        }
+
+        new_tcb.keepalive_probes += 1;
+        new_tcb.keepalive_expiry = Some(now + keepalive.interval);
+
+        let probe = new_tcb.create_segment(
+            new_tcb.send_sequence.unacknowledged - 1,
+            ControlBits::get_ack(),
+            vec![],
+        );
+
+        TCPStateChange::WithResponse(new_tcb, probe)
+    }
+
+    /// Restarts the idle time of the keepalive timer if the peer has shown it is still there,
+    /// for as long as the connection is established.
+    /// Only acceptable segments count as activity, anyone can send segments outside of the window.
+    /// A running timer is left alone so a busy connection does not pile up timers, it re-arms itself once it expires.
+    fn restart_keepalive_timer(state_change: TCPStateChange, activity: bool) -> TCPStateChange {
+        let (mut new_tcb, segments) = match state_change {
+            TCPStateChange::WithResponse(new_tcb, segment) => (new_tcb, vec![segment]),
+            TCPStateChange::WithResponses(new_tcb, segments) => (new_tcb, segments),
+            TCPStateChange::NoResponse(new_tcb) => (new_tcb, vec![]),
+            state_change => return state_change,
+        };
+
+        let established = matches!(new_tcb.state, TcpState::Established | TcpState::CloseWait);
+        let now = Instant::now();
+        if activity {
+            new_tcb.keepalive_probes = 0;
+            new_tcb.last_activity = Some(now);
+        }
+        new_tcb.keepalive_expiry = match (new_tcb.keepalive, new_tcb.keepalive_expiry) {
+            (Some(_), Some(expiry)) if established => Some(expiry),
+            (Some(keepalive), None) if established => Some(now + keepalive.idle),
+            _ => None,
+        };
+
+        TCPStateChange::with_responses(new_tcb, segments)
+    }
+
+    /// Probes the zero window of the peer with a segment carrying an old sequence number,
+    /// which the peer has to answer with an ACK announcing its current window.
+    /// The probes back off exponentially but continue for as long as the peer keeps answering.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.8.6.1
+    fn on_persist_timeout(&self) -> TCPStateChange {
+        let mut new_tcb = self.clone();
+        new_tcb.window_probes += 1;
+        new_tcb.persist_expiry = Some(Instant::now() + new_tcb.persist_timeout());
+
+        let probe = new_tcb.create_segment(
+            new_tcb.send_sequence.unacknowledged - 1,
+            ControlBits::get_ack(),
+            vec![],
+        );
+
+        TCPStateChange::WithResponse(new_tcb, probe)
+    }
+
+    /// How long to wait before the next window probe, doubling the retransmission timeout for every probe.
+    fn persist_timeout(&self) -> Duration {
+        let timeout = self.retransmission_queue.retransmission_timeout;
+
+        timeout
+            .checked_mul(1 << self.window_probes.min(16))
+            .unwrap_or(MAX_PERSIST_TIMEOUT)
+            .min(MAX_PERSIST_TIMEOUT)
+    }
+
+    /// Sends the acknowledgement we have delayed for too long.
+    fn on_delayed_acknowledgement_timeout(&self) -> TCPStateChange {
+        let new_tcb = TCB {
+            unacknowledged_received: 0,
+            delayed_acknowledgement_expiry: None,
+            ..self.clone()
+        };
+        let ack =
+            new_tcb.create_segment(new_tcb.send_sequence.next, ControlBits::get_ack(), vec![]);
+
+        TCPStateChange::WithResponse(new_tcb, ack)
+    }
+
+    /// Handles an expired retransmission timer by retransmitting the oldest unacknowledged segment,
+    /// giving up on the connection once the segment has been retransmitted too many times.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc6298#section-5
+    fn on_retransmission_timeout(&self) -> TCPStateChange {
+        if self.retransmission_queue.is_exhausted() {
+            return TCPStateChange::TimedOut;
+        }
+
+        let now = Instant::now();
+        let mut new_tcb = self.clone();
+        let segment = match new_tcb.retransmission_queue.on_timeout(now) {
+            Some(segment) => new_tcb.refresh_retransmission(segment),
+            None => return TCPStateChange::NoResponse(new_tcb),
+        };
+
+        // The window is only reduced once, when the segment is first retransmitted because of a timeout,
+        // and fast recovery ends. Duplicate acknowledgements of what was sent before do not signal a new loss.
+        // As specified in https://datatracker.ietf.org/doc/html/rfc5681#section-3.1
+        // and https://datatracker.ietf.org/doc/html/rfc6582#section-3.2
+        if new_tcb.retransmission_queue.retries == 1 {
+            let flight_size = new_tcb.send_sequence.next - new_tcb.send_sequence.unacknowledged;
+            new_tcb
+                .congestion_control
+                .on_retransmission_timeout(flight_size, now);
+        }
+        new_tcb.fast_recovery = false;
+        new_tcb.duplicate_acknowledgements = 0;
+        new_tcb.recover = Some(new_tcb.send_sequence.next);
+
+        TCPStateChange::WithResponse(new_tcb, segment)
+    }
+
+    /// Lets a segment we retransmit carry our current view of the connection.
+    fn refresh_retransmission(&self, mut segment: TCP) -> TCP {
+        if segment.control_bits.ack {
+            segment.acknowledgement_number = self.receive_sequence.next;
+        }
+        segment.window = self.advertised_window(segment.control_bits.syn);
+
+        for option in segment.options.iter_mut() {
+            if let TcpOption::Timestamps { value, echo_reply } = option {
+                *value = TCB::timestamp_clock();
+                *echo_reply = self.timestamp_recent;
+            }
+        }
+
+        segment
+    }
+
+    /// Adapts the congestion window to the acknowledgement in the segment and retransmits what the peer has lost,
+    /// ahead of any new data. A loss is detected by DupThresh duplicate acknowledgements or the SACK scoreboard,
+    /// after which we are in fast recovery until everything sent before the loss was detected is acknowledged.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc5681#section-3.2,
+    /// https://datatracker.ietf.org/doc/html/rfc6582#section-3.2 and https://datatracker.ietf.org/doc/html/rfc6675#section-5
+    fn control_congestion(&self, state_change: TCPStateChange, segment: &TCP) -> TCPStateChange {
+        let (mut new_tcb, mut segments) = match state_change {
+            TCPStateChange::WithResponse(new_tcb, segment) => (new_tcb, vec![segment]),
+            TCPStateChange::WithResponses(new_tcb, segments) => (new_tcb, segments),
+            TCPStateChange::NoResponse(new_tcb) => (new_tcb, vec![]),
+            state_change => return state_change,
+        };
+
+        // Acknowledgements of our SYN say nothing about congestion, and neither do segments we reject,
+        // whose acknowledgement and SACK blocks are not processed.
+        if !segment.control_bits.ack
+            || !self.is_syn_acknowledged()
+            || self.check_sequence_number(segment).is_some()
+        {
+            return TCPStateChange::with_responses(new_tcb, segments);
+        }
+
+        // Segments the peer has acknowledged cumulatively are neither SACKed nor lost.
+        let now = Instant::now();
+        let unacknowledged = new_tcb.send_sequence.unacknowledged;
+        let time_segments = !new_tcb.timestamps_enabled;
+        new_tcb
+            .retransmission_queue
+            .acknowledge(unacknowledged, now, time_segments);
+
+        let acknowledged = unacknowledged - self.send_sequence.unacknowledged;
+        let flight_size = new_tcb.send_sequence.next - unacknowledged;
+        // Whether everything sent before the last loss was detected has been acknowledged,
+        // until then duplicate acknowledgements do not signal a new loss.
+        let recovered = new_tcb
+            .recover
+            .is_none_or(|recover| unacknowledged >= recover);
+        let mut lost = vec![];
+
+        if acknowledged > 0 {
+            new_tcb.duplicate_acknowledgements = 0;
+
+            if !new_tcb.fast_recovery {
+                let round_trip_time = new_tcb.retransmission_queue.smoothed_round_trip_time;
+                new_tcb
+                    .congestion_control
+                    .on_acknowledgement(acknowledged, now, round_trip_time);
+            } else if recovered {
+                new_tcb.fast_recovery = false;
+                new_tcb.congestion_control.on_recovery_exit(flight_size);
+            } else {
+                // A partial acknowledgement, the segment after the acknowledged data is lost as well.
+                new_tcb
+                    .congestion_control
+                    .on_partial_acknowledgement(acknowledged);
+                lost.extend(new_tcb.retransmission_queue.mark_first_lost());
+            }
+        } else if self.is_duplicate_acknowledgement(segment) {
+            new_tcb.duplicate_acknowledgements += 1;
+
+            if new_tcb.fast_recovery {
+                new_tcb.congestion_control.on_duplicate_acknowledgement();
+            } else if new_tcb.duplicate_acknowledgements == DUPLICATE_THRESHOLD && recovered {
+                new_tcb.enter_fast_recovery(flight_size, now);
+                lost.extend(new_tcb.retransmission_queue.mark_first_lost());
+            }
+        }
+
+        let blocks: Vec<SackBlock> = segment
+            .options
+            .iter()
+            .filter_map(|option| match option {
+                TcpOption::Sack(blocks) => Some(blocks.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        if new_tcb.sack_permitted && !blocks.is_empty() {
+            let sack_lost = new_tcb.retransmission_queue.process_sack(&blocks);
+            if !sack_lost.is_empty() && !new_tcb.fast_recovery && recovered {
+                new_tcb.enter_fast_recovery(flight_size, now);
+            }
+            lost.extend(sack_lost);
+        }
+
+        for lost in lost {
+            segments.push(new_tcb.refresh_retransmission(lost));
+        }
+
+        TCPStateChange::with_responses(new_tcb, segments)
+    }
+
+    /// Enters fast recovery after a loss, until everything sent so far has been acknowledged.
+    fn enter_fast_recovery(&mut self, flight_size: u32, now: Instant) {
+        self.fast_recovery = true;
+        self.recover = Some(self.send_sequence.next);
+        self.congestion_control
+            .on_congestion_event(flight_size, now);
+    }
+
+    /// Whether the segment is a duplicate acknowledgement, i.e. it acknowledges nothing new
+    /// while we have data in flight and carries neither data nor a window update.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc5681#section-2
+    fn is_duplicate_acknowledgement(&self, segment: &TCP) -> bool {
+        let send_sequence = &self.send_sequence;
+
+        segment.control_bits.ack
+            && !send_sequence.is_everything_acknowledged()
+            && segment.data.is_empty()
+            && !segment.control_bits.syn
+            && !segment.control_bits.fin
+            && segment.acknowledgement_number == send_sequence.unacknowledged
+            && self.segment_window(segment) == send_sequence.window
+    }
+
+    /// Holds back the acknowledgement of data that arrived in sequence, until a second full-sized segment
+    /// arrives or the delayed acknowledgement timer expires, so it can be sent along with data of our own.
+    /// Anything else is acknowledged right away: segments that are out of order or fill a gap,
+    /// so the peer learns about losses quickly, a FIN, and segments we do not accept.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc1122#section-4.2.3.2
+    /// and https://datatracker.ietf.org/doc/html/rfc5681#section-4.2
+    fn delay_acknowledgement(&self, state_change: TCPStateChange, segment: &TCP) -> TCPStateChange {
+        let (mut new_tcb, segments) = match state_change {
+            TCPStateChange::WithResponse(new_tcb, segment) => (new_tcb, vec![segment]),
+            TCPStateChange::WithResponses(new_tcb, segments) => (new_tcb, segments),
+            TCPStateChange::NoResponse(new_tcb) => (new_tcb, vec![]),
+            state_change => return state_change,
+        };
+
+        let in_sequence = segment.sequence_number == self.receive_sequence.next
+            && self.out_of_order_queue.segments.is_empty()
+            && new_tcb.out_of_order_queue.segments.is_empty();
+        // A zero window accepts nothing, so a window probe carrying data is acknowledged right away.
+        let accepted = new_tcb.receive_sequence.next - self.receive_sequence.next;
+        let delayable = self.is_syn_acknowledged()
+            && in_sequence
+            && accepted > 0
+            && accepted as usize == segment.data.len()
+            && !segment.control_bits.syn
+            && !segment.control_bits.fin
+            && !segment.control_bits.rst
+            && self.check_sequence_number(segment).is_none();
+        if !delayable {
+            return TCPStateChange::with_responses(new_tcb, segments);
+        }
+
+        // A full-sized segment of the peer carries the MSS we announced less the options it sends along.
+        let options_length = TcpOption::serialize_options(&segment.options).len() as u32;
+        let full_sized =
+            (new_tcb.receive_maximum_segment_size as u32).saturating_sub(options_length);
+        new_tcb.unacknowledged_received += accepted;
+        if new_tcb.unacknowledged_received >= 2 * full_sized {
+            return TCPStateChange::with_responses(new_tcb, segments);
+        }
+
+        if new_tcb.delayed_acknowledgement_expiry.is_none() {
+            new_tcb.delayed_acknowledgement_expiry =
+                Some(Instant::now() + DELAYED_ACKNOWLEDGEMENT_TIMEOUT);
+        }
+
+        let segments = segments
+            .into_iter()
+            .filter(|segment| !TCB::is_pure_acknowledgement(segment))
+            .collect();
+
+        TCPStateChange::with_responses(new_tcb, segments)
+    }
+
+    /// Sends whatever the state change leaves in the send buffer that the peer's window allows,
+    /// together with any segments the state change already sends.
+    /// Every segment we send acknowledges everything received so far, so a pure ACK is left out
+    /// when it can piggyback on another segment and no acknowledgement remains delayed.
+    fn send_pending_data(state_change: TCPStateChange) -> TCPStateChange {
+        let (new_tcb, mut segments) = match state_change {
+            TCPStateChange::WithResponse(new_tcb, segment) => (new_tcb, vec![segment]),
+            TCPStateChange::WithResponses(new_tcb, segments) => (new_tcb, segments),
+            TCPStateChange::NoResponse(new_tcb) => (new_tcb, vec![]),
+            state_change => return state_change,
+        };
+
+        let (mut new_tcb, data_segments) = new_tcb.segment_send_buffer();
+        segments.extend(data_segments);
+
+        if segments
+            .iter()
+            .any(|segment| !TCB::is_pure_acknowledgement(segment))
+        {
+            segments.retain(|segment| !TCB::is_pure_acknowledgement(segment));
+        }
+        if segments.iter().any(|segment| segment.control_bits.ack) {
+            new_tcb.unacknowledged_received = 0;
+            new_tcb.delayed_acknowledgement_expiry = None;
+        }
+
+        TCPStateChange::with_responses(new_tcb, segments)
+    }
+
+    /// Whether the segment only acknowledges, i.e. it carries no data and no control bits but ACK.
+    fn is_pure_acknowledgement(segment: &TCP) -> bool {
+        let control_bits = &segment.control_bits;
+
+        control_bits.ack
+            && segment.data.is_empty()
+            && !control_bits.syn
+            && !control_bits.fin
+            && !control_bits.rst
+    }
+
+    /// Sends whatever the state change leaves in the send buffer, tracks it for retransmission
+    /// and takes care of probing the peer's window if it is closed.
+    fn transmit(state_change: TCPStateChange) -> TCPStateChange {
+        TCB::update_persist_timer(TCB::track_retransmissions(TCB::send_pending_data(
+            state_change,
+        )))
+    }
+
+    /// Starts the persist timer when the peer's window is closed while we have data to send
+    /// and nothing in flight whose acknowledgement could reopen it, stopping it otherwise.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.8.6.1
+    fn update_persist_timer(state_change: TCPStateChange) -> TCPStateChange {
+        let (mut new_tcb, segments) = match state_change {
+            TCPStateChange::WithResponse(new_tcb, segment) => (new_tcb, vec![segment]),
+            TCPStateChange::WithResponses(new_tcb, segments) => (new_tcb, segments),
+            TCPStateChange::NoResponse(new_tcb) => (new_tcb, vec![]),
+            state_change => return state_change,
+        };
+
+        let sent = (new_tcb.send_sequence.next - new_tcb.send_buffer_sequence()) as usize;
+        let unsent = sent < new_tcb.send_buffer.len();
+        let window_closed = new_tcb.send_sequence.window == 0;
+        let in_flight = !new_tcb.retransmission_queue.segments.is_empty();
+
+        if new_tcb.is_syn_acknowledged() && window_closed && unsent && !in_flight {
+            if new_tcb.persist_expiry.is_none() {
+                new_tcb.persist_expiry = Some(Instant::now() + new_tcb.persist_timeout());
+            }
+        } else {
+            new_tcb.persist_expiry = None;
+            new_tcb.window_probes = 0;
+        }
+
+        TCPStateChange::with_responses(new_tcb, segments)
+    }
+
+    /// Drops everything the peer has acknowledged from the retransmission queue
+    /// and queues anything we are sending that occupies sequence space.
+    fn track_retransmissions(state_change: TCPStateChange) -> TCPStateChange {
+        let now = Instant::now();
+
+        let (mut new_tcb, segments) = match state_change {
+            TCPStateChange::WithResponse(new_tcb, segment) => (new_tcb, vec![segment]),
+            TCPStateChange::WithResponses(new_tcb, segments) => (new_tcb, segments),
+            TCPStateChange::NoResponse(new_tcb) => (new_tcb, vec![]),
+            state_change => return state_change,
+        };
+
+        let unacknowledged = new_tcb.send_sequence.unacknowledged;
+        let time_segments = !new_tcb.timestamps_enabled;
+        new_tcb
+            .retransmission_queue
+            .acknowledge(unacknowledged, now, time_segments);
+        for segment in &segments {
+            new_tcb.retransmission_queue.push(segment, now);
+        }
+
+        TCPStateChange::with_responses(new_tcb, segments)
+    }
+
+    /// Cuts the data in the send buffer that has not been sent yet into segments,
+    /// sending no more than the peer's window & the maximum segment size allow.
+    /// Once all data has been sent the FIN follows if we are closing.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.8.6.2.1
+    fn segment_send_buffer(&self) -> (TCB, Vec<TCP>) {
+        let sends_fin = matches!(
+            self.state,
+            TcpState::FinWait1 | TcpState::CLosing | TcpState::LastAck
+        );
+        if !sends_fin && !matches!(self.state, TcpState::Established | TcpState::CloseWait) {
+            return (self.clone(), vec![]);
+        }
+
+        let mut new_tcb = self.clone();
+        let mut segments = vec![];
+
+        let start = self.send_buffer_sequence();
+        // Data can only follow our SYN once it has been acknowledged.
+        let syn_acknowledged = self.is_syn_acknowledged();
+        // We send no more than the peer can receive, nor more than the network can handle.
+        let window = self
+            .send_sequence
+            .window
+            .min(self.congestion_control.congestion_window());
+
+        loop {
+            let sent = (new_tcb.send_sequence.next - start) as usize;
+            if !syn_acknowledged || sent >= new_tcb.send_buffer.len() {
+                break;
+            }
+
+            // The part of the window that is not taken up by data in flight.
+            let window_end = new_tcb.send_sequence.unacknowledged + window;
+            if window_end <= new_tcb.send_sequence.next {
+                break;
+            }
+            let usable_window = window_end - new_tcb.send_sequence.next;
+
+            let maximum_segment_size = new_tcb.effective_send_maximum_segment_size();
+            let length = (new_tcb.send_buffer.len() - sent)
+                .min(usable_window as usize)
+                .min(maximum_segment_size);
+            let end = sent + length;
+
+            // Nagle's algorithm, a segment smaller than the maximum segment size waits for as long as
+            // any data is unacknowledged so small writes are coalesced. Closing flushes whatever is left.
+            // As specified in https://datatracker.ietf.org/doc/html/rfc1122#section-4.2.3.4
+            // Urgent data is not held back either.
+            let in_flight = !new_tcb.send_sequence.is_everything_acknowledged();
+            let urgent = new_tcb
+                .send_sequence
+                .urgent_pointer
+                .is_some_and(|end| end > new_tcb.send_sequence.next);
+            let held_back = !new_tcb.no_delay && !sends_fin && !urgent;
+            if length < maximum_segment_size && in_flight && held_back {
+                break;
+            }
+
+            // Push the last of the data we have to the application of the peer.
+            let control_bits = if end == new_tcb.send_buffer.len() {
+                ControlBits::get_psh_ack()
+            } else {
+                ControlBits::get_ack()
+            };
+
+            segments.push(new_tcb.create_segment(
+                new_tcb.send_sequence.next,
+                control_bits,
+                new_tcb.send_buffer[sent..end].to_vec(),
+            ));
+            new_tcb.send_sequence.next += length as u32;
+        }
+
+        let everything_sent =
+            (new_tcb.send_sequence.next - start) as usize == new_tcb.send_buffer.len();
+        if sends_fin && everything_sent && new_tcb.fin_sequence.is_none() {
+            segments.push(new_tcb.create_segment(
+                new_tcb.send_sequence.next,
+                ControlBits::get_fin_ack(),
+                vec![],
+            ));
+            new_tcb.fin_sequence = Some(new_tcb.send_sequence.next);
+            new_tcb.send_sequence.next += 1; // FIN takes 1 sequence number.
+        }
+
+        (new_tcb, segments)
+    }
+
+    /// Whether the peer has acknowledged our SYN.
+    fn is_syn_acknowledged(&self) -> bool {
+        self.syn_acknowledged
+    }
+
+    /// The sequence number of the first byte in the send buffer,
+    /// which follows our SYN for as long as the SYN has not been acknowledged.
+    fn send_buffer_sequence(&self) -> SequenceNumber {
+        let unacknowledged = self.send_sequence.unacknowledged;
+        if !self.is_syn_acknowledged() {
+            unacknowledged + 1 // SYN takes 1 sequence number.
+        } else {
+            unacknowledged
+        }
+    }
+
+    /// The largest amount of data that fits in a segment next to the options it carries.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.7.1
+    fn effective_send_maximum_segment_size(&self) -> usize {
+        let options_length = TcpOption::serialize_options(&self.segment_options()).len();
+
+        (self.send_maximum_segment_size as usize).saturating_sub(options_length)
+    }
+
+    /// Whether we have sent our FIN and the peer has acknowledged it.
+    pub fn is_fin_acknowledged(&self) -> bool {
+        self.fin_sequence
+            .is_some_and(|fin| self.send_sequence.unacknowledged > fin)
+    }
+
+    /// The options we send with our SYN.
+    pub fn syn_options(&self) -> Vec<TcpOption> {
+        let mut options = vec![TcpOption::MaximumSegmentSize(
+            self.receive_maximum_segment_size,
+        )];
+
+        // We always scale our window, so a shift count of 0 means the peer's SYN did not offer window scaling.
+        if self.receive_window_shift > 0 {
+            options.push(TcpOption::NoOperation); // Aligns the window scale option to 32 bits.
+            options.push(TcpOption::WindowScale(self.receive_window_shift));
+        }
+
+        if self.sack_permitted {
+            // Aligns the SACK-permitted option to 32 bits.
+            options.push(TcpOption::NoOperation);
+            options.push(TcpOption::NoOperation);
+            options.push(TcpOption::SackPermitted);
+        }
+
+        if self.timestamps_enabled {
+            options.push(TcpOption::NoOperation); // Aligns the timestamps to 32 bits.
+            options.push(TcpOption::NoOperation);
+            options.push(self.timestamps_option());
+        }
+
+        options
+    }
+
+    /// The options we send with any segment other than a SYN.
+    /// Data that arrived out of order is reported to the peer, the block containing the latest segment first.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc2018#section-4
+    pub fn segment_options(&self) -> Vec<TcpOption> {
+        let mut options = vec![];
+
+        if self.timestamps_enabled {
+            options.push(TcpOption::NoOperation); // Aligns the timestamps to 32 bits.
+            options.push(TcpOption::NoOperation);
+            options.push(self.timestamps_option());
+        }
+
+        let mut blocks = self
+            .out_of_order_queue
+            .sack_blocks(self.receive_sequence.next);
+        if self.sack_permitted && !blocks.is_empty() {
+            blocks.truncate(if self.timestamps_enabled {
+                MAX_SACK_BLOCKS_WITH_TIMESTAMPS
+            } else {
+                MAX_SACK_BLOCKS
+            });
+
+            options.push(TcpOption::NoOperation); // Aligns the SACK blocks to 32 bits.
+            options.push(TcpOption::NoOperation);
+            options.push(TcpOption::Sack(blocks));
+        }
+
+        options
+    }
+
+    /// The timestamps we send, echoing TS.Recent.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc7323#section-3.2
+    fn timestamps_option(&self) -> TcpOption {
+        TcpOption::Timestamps {
+            value: TCB::timestamp_clock(),
+            echo_reply: self.timestamp_recent,
+        }
+    }
+
+    /// Our timestamp clock (TSval), which ticks every millisecond and wraps around.
+    fn timestamp_clock() -> u32 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u32
+    }
+
+    /// The timestamp value & echo reply of the segment, if it carries the Timestamps option.
+    fn segment_timestamps(segment: &TCP) -> Option<(u32, u32)> {
+        segment.options.iter().find_map(|option| match option {
+            TcpOption::Timestamps { value, echo_reply } => Some((*value, *echo_reply)),
+            _ => None,
+        })
+    }
+
+    /// The timestamp of the peer to echo if both SYNs carry timestamps, None if timestamps are not used.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc7323#section-3.2
+    pub fn negotiate_timestamps(&self, syn: &TCP) -> Option<u32> {
+        if !self.timestamps_enabled {
+            return None;
+        }
+
+        TCB::segment_timestamps(syn).map(|(value, _)| value)
+    }
+
+    /// Whether the segment is an old duplicate, i.e. it carries a timestamp older than TS.Recent,
+    /// in which case it is rejected to protect against wrapped sequence numbers (PAWS).
+    /// A RST is never rejected, and neither is any segment once TS.Recent has expired.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc7323#section-5.3
+    fn is_timestamp_outdated(&self, segment: &TCP) -> bool {
+        if !self.timestamps_enabled || segment.control_bits.rst {
+            return false;
+        }
+
+        let value = match TCB::segment_timestamps(segment) {
+            Some((value, _)) => value,
+            None => return false,
+        };
+
+        let expired = self
+            .timestamp_recent_time
+            .is_none_or(|recorded| recorded.elapsed() > TIMESTAMP_RECENT_LIFETIME);
+
+        // Timestamps wrap around, so they are compared like sequence numbers.
+        !expired && SequenceNumber(value) < SequenceNumber(self.timestamp_recent)
+    }
+
+    /// Records the timestamp of an acceptable segment as TS.Recent if it is newer and the segment
+    /// does not lie beyond what we have acknowledged, so we echo the timestamp of the oldest unacknowledged segment.
+    /// The segment's echo reply measures the round trip time (RTTM) if it acknowledges new data.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc7323#section-4.3
+    fn process_timestamps(&self, segment: &TCP) -> TCB {
+        let mut new_tcb = self.clone();
+        let (value, echo_reply) = match TCB::segment_timestamps(segment) {
+            Some(timestamps) if self.timestamps_enabled => timestamps,
+            _ => return new_tcb,
+        };
+
+        // What we last acknowledged (Last.ACK.sent), which lags behind RCV.NXT while our acknowledgement is delayed
+        // so we echo the timestamp of the first segment it covers.
+        let last_acknowledgement_sent = self.receive_sequence.next - self.unacknowledged_received;
+        let newer = SequenceNumber(value) >= SequenceNumber(self.timestamp_recent);
+        if newer && segment.sequence_number <= last_acknowledgement_sent {
+            new_tcb.timestamp_recent = value;
+            new_tcb.timestamp_recent_time = Some(Instant::now());
+        }
+
+        let una = self.send_sequence.unacknowledged;
+        let ack = segment.acknowledgement_number;
+        let acknowledges_new_data = ack > una && ack <= self.send_sequence.next;
+        let round_trip_time = TCB::timestamp_clock().wrapping_sub(echo_reply);
+        if segment.control_bits.ack && acknowledges_new_data && round_trip_time < 1 << 31 {
+            new_tcb
+                .retransmission_queue
+                .sample_round_trip_time(Duration::from_millis(round_trip_time as u64));
+        }
+
+        new_tcb
+    }
+
+    /// The TCB with the options announced in the SYN of the peer negotiated,
+    /// i.e. the maximum segment size, window scaling, SACK & timestamps.
+    /// The congestion control starts over with the negotiated maximum segment size.
+    pub fn negotiate_syn_options(&self, syn: &TCP) -> TCB {
+        let (send_window_shift, receive_window_shift) = self.negotiate_window_shifts(syn);
+        let timestamp_recent = self.negotiate_timestamps(syn);
+        let send_maximum_segment_size = self.negotiate_maximum_segment_size(syn);
+
+        TCB {
+            send_maximum_segment_size,
+            send_window_shift,
+            receive_window_shift,
+            sack_permitted: self.negotiate_sack_permitted(syn),
+            timestamps_enabled: timestamp_recent.is_some(),
+            timestamp_recent: timestamp_recent.unwrap_or(0),
+            timestamp_recent_time: Some(Instant::now()),
+            congestion_control: self
+                .congestion_control
+                .algorithm()
+                .create(send_maximum_segment_size),
+            ..self.clone()
+        }
+    }
+
+    /// Whether both ends selectively acknowledge data, which is the case if the SYN of the peer permits it.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc2018#section-2
+    pub fn negotiate_sack_permitted(&self, syn: &TCP) -> bool {
+        syn.options.contains(&TcpOption::SackPermitted)
+    }
+
+    /// The shift counts of the windows of the peer and ours, as announced in the Window Scale options of the SYNs.
+    /// Window scaling is only used if both SYNs carry the option.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc7323#section-2.2
+    pub fn negotiate_window_shifts(&self, syn: &TCP) -> (u8, u8) {
+        let announced = syn.options.iter().find_map(|option| match option {
+            TcpOption::WindowScale(shift) => Some(*shift),
+            _ => None,
+        });
+
+        match announced {
+            Some(shift) => (shift.min(MAX_WINDOW_SHIFT), RECEIVE_WINDOW_SHIFT),
+            None => (0, 0),
+        }
+    }
+
+    /// The window the peer advertises in the segment, which is never scaled in a SYN.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc7323#section-2.3
+    pub fn segment_window(&self, segment: &TCP) -> u32 {
+        if segment.control_bits.syn {
+            return segment.window as u32;
+        }
+
+        (segment.window as u32) << self.send_window_shift
+    }
+
+    /// The window field advertising our receive window in a segment, which is never scaled in a SYN.
+    pub fn advertised_window(&self, syn: bool) -> u16 {
+        let window = if syn {
+            self.receive_sequence.window
+        } else {
+            self.receive_sequence.window >> self.receive_window_shift
+        };
+
+        window.min(u16::MAX as u32) as u16
+    }
+
+    /// The largest amount of data we may send in a single segment, which is what the peer announces in its SYN
+    /// or the default if it does not, but never more than fits on our own link.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.7.1
+    pub fn negotiate_maximum_segment_size(&self, syn: &TCP) -> u16 {
+        let announced = syn
+            .options
+            .iter()
+            .find_map(|option| match option {
+                TcpOption::MaximumSegmentSize(size) => Some(*size),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_MAXIMUM_SEGMENT_SIZE);
+
+        announced.min(self.receive_maximum_segment_size)
+    }
+
+    /// Creates a segment from our end of the connection acknowledging everything received so far.
+    /// Up to the end of the urgent data every segment points to it, as far as the 16-bit urgent pointer reaches.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc6093#section-4
+    pub fn create_segment(
+        &self,
+        sequence_number: SequenceNumber,
+        mut control_bits: ControlBits,
+        data: Vec<u8>,
+    ) -> TCP {
+        let options = if control_bits.syn {
+            self.syn_options()
+        } else {
+            self.segment_options()
+        };
+        let window = self.advertised_window(control_bits.syn);
+
+        let urgent_pointer = match self.send_sequence.urgent_pointer {
+            Some(end) if end > sequence_number && !control_bits.syn => {
+                control_bits.urg = true;
+                (end - sequence_number).min(u16::MAX as u32) as u16
+            }
+            _ => 0,
+        };
+
+        TCP {
+            src_port: self.local_port,
+            dst_port: self.remote_port,
+            sequence_number,
+            acknowledgement_number: self.receive_sequence.next,
+            data_offset: TCP::calculate_data_offset(&options),
+            reserved: 0,
+            control_bits,
+            window,
+            checksum: 0,
+            urgent_pointer,
+            options,
+            data,
+        }
+    }
+
+    /// Checks whether the sequence number of a segment received in a synchronized state is acceptable,
+    /// returning the resulting state change if it is not and the segment should not be processed any further.
+    /// An unacceptable segment is acknowledged, unless it is a RST, to let the peer know what we expect.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.7.4
+    pub fn check_sequence_number(&self, segment: &TCP) -> Option<TCPStateChange> {
+        // Once timestamps are in use every segment but a RST has to carry them, or it is dropped.
+        // As specified in https://datatracker.ietf.org/doc/html/rfc7323#section-3.2
+        let missing_timestamps = TCB::segment_timestamps(segment).is_none();
+        if self.timestamps_enabled && !segment.control_bits.rst && missing_timestamps {
+            return Some(TCPStateChange::NoResponse(self.clone()));
+        }
+
+        let outdated = self.is_timestamp_outdated(segment);
+        if !outdated && self.receive_sequence.is_segment_acceptable(segment) {
+            return None;
+        }
+
+        if segment.control_bits.rst {
+            return Some(TCPStateChange::NoResponse(self.clone()));
+        }
+
+        let ack = self.create_segment(self.send_sequence.next, ControlBits::get_ack(), vec![]);
+        Some(TCPStateChange::WithResponse(self.clone(), ack))
+    }
+
+    /// Processes the RST & SYN bits of a segment received in a synchronized state,
+    /// returning the resulting state change if the segment should not be processed any further.
+    /// To protect against blind reset attacks only a RST matching RCV.NXT exactly resets the connection,
+    /// while an in-window RST or any SYN is answered with a challenge ACK,
+    /// as specified in https://datatracker.ietf.org/doc/html/rfc5961#section-3.2
+    pub fn process_reset_and_syn(&self, segment: &TCP) -> Option<TCPStateChange> {
+        let challenge_ack = || {
+            let ack = self.create_segment(self.send_sequence.next, ControlBits::get_ack(), vec![]);
+            TCPStateChange::WithResponse(self.clone(), ack)
+        };
+
+        if segment.control_bits.rst {
+            let next = self.receive_sequence.next;
+
+            return Some(if segment.sequence_number == next {
+                TCPStateChange::Reset(None)
+            } else if segment
+                .sequence_number
+                .is_within(next, self.receive_sequence.window)
+            {
+                challenge_ack()
+            } else {
+                TCPStateChange::NoResponse(self.clone())
+            });
+        }
+
+        if segment.control_bits.syn {
+            return Some(challenge_ack());
+        }
+
+        None
+    }
+
+    /// Processes the acknowledgement field of the segment, returning the TCB with the updated send sequence,
+    /// from which the acknowledged data has been released.
+    /// Only acknowledgements of something not yet acknowledged (SND.UNA < SEG.ACK =< SND.NXT) advance SND.UNA,
+    /// while the send window is updated from any segment that is not older than the last window update.
+    /// The timestamps of the segment are processed first.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.7.4
+    pub fn process_acknowledgement(&self, segment: &TCP) -> TCB {
+        let tcb = self.process_timestamps(segment);
+        let una = tcb.send_sequence.unacknowledged;
+        let ack = segment.acknowledgement_number;
+
+        // The ACK is older than SND.UNA, or acknowledges something we have not sent yet.
+        if ack - una > tcb.send_sequence.next - una {
+            return tcb;
+        }
+
+        let mut send_sequence = SendSequence {
+            unacknowledged: ack,
+            ..tcb.send_sequence.clone()
+        };
+
+        // The peer has received all urgent data.
+        if send_sequence.urgent_pointer.is_some_and(|end| ack >= end) {
+            send_sequence.urgent_pointer = None;
+        }
+
+        let wl1 = send_sequence.last_window_update_sequence;
+        let wl2 = send_sequence.last_window_update_ack;
+        let newer_sequence = segment.sequence_number > wl1;
+        let newer_ack = segment.sequence_number == wl1 && ack >= wl2;
+        if newer_sequence || newer_ack {
+            send_sequence.window = tcb.segment_window(segment);
+            send_sequence.last_window_update_sequence = segment.sequence_number;
+            send_sequence.last_window_update_ack = ack;
+        }
+
+        // Release the data that no longer has to be retransmitted, the ACK of our FIN acknowledges no data.
+        let buffer_sequence = tcb.send_buffer_sequence();
+        let acknowledged_data = if ack > buffer_sequence {
+            (ack - buffer_sequence) as usize
+        } else {
+            0
+        };
+        let mut send_buffer = tcb.send_buffer.to_owned();
+        send_buffer.drain(..acknowledged_data.min(send_buffer.len()));
+
+        TCB {
+            // Our SYN is the first thing any acknowledgement covers.
+            syn_acknowledged: self.syn_acknowledged || ack != una,
+            send_sequence,
+            send_buffer,
+            ..tcb
+        }
+    }
+
+    /// The most data we buffer for the application, as far as a window scaled by the shift count can advertise it.
+    pub fn receive_buffer_capacity(receive_window_shift: u8) -> u32 {
+        RECEIVE_BUFFER_CAPACITY.min((u16::MAX as u32) << receive_window_shift)
+    }
+
+    /// The receive window (RCV.WND) once RCV.NXT has advanced to next with buffered bytes waiting to be read.
+    /// The right edge of the window never moves back, and only moves forward once the window can open
+    /// by half the receive buffer or a full segment, so the peer is not lured into sending tiny segments.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.8.6.2.2
+    fn receive_window(&self, next: SequenceNumber, buffered: usize) -> u32 {
+        let right_edge = self.receive_sequence.next + self.receive_sequence.window;
+        let window = if right_edge > next {
+            right_edge - next
+        } else {
+            0
+        };
+
+        let capacity = TCB::receive_buffer_capacity(self.receive_window_shift);
+        let available = capacity.saturating_sub(buffered as u32);
+        let threshold = (capacity / 2).min(self.receive_maximum_segment_size as u32);
+
+        if available >= window + threshold {
+            available
+        } else {
+            window
+        }
+    }
+
+    /// Receives the data of the segment, returning the TCB with the updated receive sequence & buffer.
+    /// Data ahead of RCV.NXT is queued until the gap before it has been filled,
+    /// after which it is appended to the receive buffer together with the data that filled the gap.
+    /// A FIN is only processed once it is next in sequence, so a FIN arriving out of order has to be retransmitted.
+    /// The last byte of urgent data is taken out of the stream once it is in sequence, leaving the urgent mark.
+    /// As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.10.7.4
+    pub fn receive_text(&self, segment: &TCP) -> TCB {
+        let mut receive_buffer = self.receive_buffer.to_owned();
+        let mut out_of_order_queue = self.out_of_order_queue.clone();
+        let next = self.receive_sequence.next;
+
+        // The number of bytes at the start of the segment that we have already received.
+        let already_received = (next - segment.sequence_number) as usize;
+
+        let mut next = if segment.sequence_number > next {
+            out_of_order_queue.insert(
+                segment.sequence_number,
+                &segment.data,
+                next,
+                self.receive_sequence.window,
+            );
+            next
+        } else if already_received < segment.data.len() {
+            // Data beyond the receive window does not fit in the receive buffer.
+            let window = self.receive_sequence.window as usize;
+            let new_data =
+                &segment.data[already_received..segment.data.len().min(already_received + window)];
+            receive_buffer.extend_from_slice(new_data);
+            next + new_data.len() as u32
+        } else {
+            next
+        };
+
+        while let Some(data) = out_of_order_queue.pop_in_sequence(next) {
+            receive_buffer.extend_from_slice(&data);
+            next += data.len() as u32;
+        }
+
+        // RCV.UP only ever moves forward, to urgent data we have not received yet.
+        let mut urgent_pointer = self.receive_sequence.urgent_pointer;
+        if let Some(end) = segment.urgent_pointer_sequence() {
+            if end > self.receive_sequence.next && urgent_pointer.is_none_or(|up| end > up) {
+                urgent_pointer = Some(end);
+            }
+        }
+
+        let mut urgent_data = self.urgent_data;
+        let mut urgent_mark = self.urgent_mark;
+        if let Some(end) = urgent_pointer {
+            let received = next - self.receive_sequence.next;
+            let last = end - 1;
+            // The last byte of urgent data has just been received in sequence.
+            if last.is_within(self.receive_sequence.next, received) {
+                let index = receive_buffer.len() - (next - last) as usize;
+                urgent_data = Some(receive_buffer.remove(index));
+                urgent_mark = Some(index);
+            }
+        }
+
+        TCB {
+            receive_sequence: ReceiveSequence {
+                next,
+                window: self.receive_window(next, receive_buffer.len()),
+                urgent_pointer,
+                ..self.receive_sequence.clone()
+            },
+            receive_buffer,
+            out_of_order_queue,
+            urgent_data,
+            urgent_mark,
+            ..self.clone()
         }
     }
 }
@@ -39,8 +1493,721 @@ impl Default for TCB {
             send_sequence: SendSequence::default(),
             receive_sequence: ReceiveSequence::default(),
             state: TcpState::Listen,
+            syn_acknowledged: false,
+            fin_sequence: None,
             send_buffer: vec![],
             receive_buffer: vec![],
+            out_of_order_queue: OutOfOrderQueue::default(),
+            urgent_data: None,
+            urgent_mark: None,
+            send_maximum_segment_size: DEFAULT_MAXIMUM_SEGMENT_SIZE,
+            receive_maximum_segment_size: DEFAULT_MAXIMUM_SEGMENT_SIZE,
+            send_window_shift: 0,
+            receive_window_shift: RECEIVE_WINDOW_SHIFT,
+            sack_permitted: true,
+            timestamps_enabled: true,
+            timestamp_recent: 0,
+            timestamp_recent_time: None,
+            time_wait_expiry: None,
+            unacknowledged_received: 0,
+            delayed_acknowledgement_expiry: None,
+            persist_expiry: None,
+            window_probes: 0,
+            keepalive: None,
+            keepalive_expiry: None,
+            keepalive_probes: 0,
+            last_activity: None,
+            retransmission_queue: RetransmissionQueue::default(),
+            no_delay: false,
+            congestion_control: CongestionControlAlgorithm::NewReno
+                .create(DEFAULT_MAXIMUM_SEGMENT_SIZE),
+            duplicate_acknowledgements: 0,
+            fast_recovery: false,
+            recover: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::ip_layer::ipv4::ipv4_address::IPAddressV4;
+    use crate::layers::ip_layer::IPAddress;
+    use crate::layers::transport_layer::tcp::retransmission_queue::MAX_RETRANSMISSIONS;
+
+    const CLIENT_PORT: u16 = 50000;
+    const SERVER_PORT: u16 = 80;
+
+    // Splits a state change into the TCB it leaves, None once the connection is deleted, and the segments it sends.
+    fn split(state_change: TCPStateChange) -> (Option<TCB>, Vec<TCP>) {
+        match state_change {
+            TCPStateChange::WithResponse(tcb, segment) => (Some(tcb), vec![segment]),
+            TCPStateChange::WithResponses(tcb, segments) => (Some(tcb), segments),
+            TCPStateChange::NoResponse(tcb) => (Some(tcb), vec![]),
+            TCPStateChange::Closed | TCPStateChange::TimedOut => (None, vec![]),
+            TCPStateChange::Reset(reset) => (None, reset.into_iter().collect()),
         }
     }
+
+    // Delivers the segments to the TCB one by one, returning the TCB they leave and everything it sends in response.
+    fn deliver(tcb: TCB, segments: &[TCP]) -> (Option<TCB>, Vec<TCP>) {
+        let mut tcb = Some(tcb);
+        let mut responses = vec![];
+        for segment in segments {
+            let current = tcb.expect("segment delivered to a deleted connection");
+            let (new_tcb, sent) = split(current.on_packet_received(segment).unwrap());
+            tcb = new_tcb;
+            responses.extend(sent);
+        }
+
+        (tcb, responses)
+    }
+
+    fn close(tcb: TCB) -> (TCB, Vec<TCP>) {
+        let (tcb, segments) = split(tcb.close().unwrap());
+        (tcb.unwrap(), segments)
+    }
+
+    // Opens a connection from a client to a listening server, returning both established TCBs.
+    fn establish() -> (TCB, TCB) {
+        let address: IPAddress = IPAddressV4(0x0a000001).into();
+        let client_quad = TCPQuad {
+            src_ip: address.clone(),
+            dst_ip: address,
+            src_port: SERVER_PORT,
+            dst_port: CLIENT_PORT,
+        };
+
+        let (client, syn) = split(TCB::connect(
+            &client_quad,
+            SequenceNumber(1000),
+            1460,
+            CongestionControlAlgorithm::NewReno,
+        ));
+        let server = TCB {
+            send_sequence: SendSequence {
+                initial_send_sequence: SequenceNumber(9000),
+                ..SendSequence::default()
+            },
+            receive_maximum_segment_size: 1460,
+            ..TCB::default()
+        };
+
+        let (server, syn_ack) = deliver(server, &syn);
+        let (client, ack) = deliver(client.unwrap(), &syn_ack);
+        let (server, _) = deliver(server.unwrap(), &ack);
+
+        let (client, server) = (client.unwrap(), server.unwrap());
+        assert_eq!(client.state, TcpState::Established);
+        assert_eq!(server.state, TcpState::Established);
+        (client, server)
+    }
+
+    fn fin_count(segments: &[TCP]) -> usize {
+        segments
+            .iter()
+            .filter(|segment| segment.control_bits.fin)
+            .count()
+    }
+
+    #[test]
+    fn active_and_passive_close() {
+        let (client, server) = establish();
+
+        let (client, fin) = close(client);
+        assert_eq!(client.state, TcpState::FinWait1);
+        assert_eq!(fin_count(&fin), 1);
+
+        let (server, ack) = deliver(server, &fin);
+        let server = server.unwrap();
+        assert_eq!(server.state, TcpState::CloseWait);
+
+        let (client, sent) = deliver(client, &ack);
+        let client = client.unwrap();
+        assert_eq!(client.state, TcpState::FinWait2);
+        assert!(client.is_fin_acknowledged());
+        assert!(sent.is_empty(), "the FIN is only sent once");
+
+        let (server, fin) = close(server);
+        assert_eq!(server.state, TcpState::LastAck);
+        assert_eq!(fin_count(&fin), 1);
+
+        let (client, ack) = deliver(client, &fin);
+        let client = client.unwrap();
+        assert_eq!(client.state, TcpState::TimeWait);
+        assert_eq!(fin_count(&ack), 0);
+
+        let (server, sent) = deliver(server, &ack);
+        assert!(
+            server.is_none(),
+            "LAST_ACK closes once the FIN is acknowledged"
+        );
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn simultaneous_close() {
+        let (client, server) = establish();
+
+        let (client, client_fin) = close(client);
+        let (server, server_fin) = close(server);
+
+        let (client, client_ack) = deliver(client, &server_fin);
+        let (server, server_ack) = deliver(server, &client_fin);
+        let (client, server) = (client.unwrap(), server.unwrap());
+        assert_eq!(client.state, TcpState::CLosing);
+        assert_eq!(server.state, TcpState::CLosing);
+        assert_eq!(fin_count(&client_ack), 0);
+        assert_eq!(fin_count(&server_ack), 0);
+
+        let (client, client_sent) = deliver(client, &server_ack);
+        let (server, server_sent) = deliver(server, &client_ack);
+        assert_eq!(client.unwrap().state, TcpState::TimeWait);
+        assert_eq!(server.unwrap().state, TcpState::TimeWait);
+        assert!(client_sent.is_empty() && server_sent.is_empty());
+    }
+
+    #[test]
+    fn time_wait_acknowledges_unacceptable_segments() {
+        let (client, server) = establish();
+
+        let (client, client_fin) = close(client);
+        let (server, ack) = deliver(server, &client_fin);
+        let (client, _) = deliver(client, &ack);
+        let (server, server_fin) = close(server.unwrap());
+        let (client, _) = deliver(client.unwrap(), &server_fin);
+        let client = client.unwrap();
+        assert_eq!(client.state, TcpState::TimeWait);
+
+        // The peer retransmits its FIN because our ACK of it was lost.
+        let (client, sent) = deliver(client, &server_fin);
+        let client = client.unwrap();
+        assert_eq!(client.state, TcpState::TimeWait);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].acknowledgement_number, client.receive_sequence.next);
+
+        // Data far beyond the window is not processed, only acknowledged.
+        let mut out_of_window = server.create_segment(
+            server.send_sequence.next + (1 << 30),
+            ControlBits::get_ack(),
+            vec![1, 2, 3],
+        );
+        out_of_window.control_bits.fin = true;
+        let (client, sent) = deliver(client, &[out_of_window]);
+        let client = client.unwrap();
+        assert_eq!(client.state, TcpState::TimeWait);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].acknowledgement_number, client.receive_sequence.next);
+        assert!(client.receive_buffer.is_empty());
+    }
+
+    #[test]
+    fn keepalive_timer_is_not_moved_by_received_segments() {
+        let (client, server) = establish();
+        let (client, _) = split(client.set_keepalive(Some(Keepalive::default())));
+        let client = client.unwrap();
+        let expiry = client.keepalive_expiry;
+        assert!(expiry.is_some());
+
+        let (_, data) = split(server.write(b"hello").unwrap());
+        let (client, _) = deliver(client, &data);
+        let client = client.unwrap();
+        assert_eq!(client.keepalive_expiry, expiry);
+
+        // Once it expires the timer re-arms itself for the idle time after the last activity, without probing.
+        let (client, sent) = split(client.on_timer_expired(TcpTimer::Keepalive));
+        let client = client.unwrap();
+        assert!(sent.is_empty());
+        assert_eq!(
+            client.keepalive_expiry,
+            client
+                .last_activity
+                .map(|activity| activity + Keepalive::default().idle)
+        );
+    }
+
+    #[test]
+    fn only_acceptable_segments_answer_keepalive_probes() {
+        let (client, server) = establish();
+        let keepalive = Keepalive {
+            idle: Duration::ZERO,
+            interval: Duration::ZERO,
+            probes: 9,
+        };
+        let (client, _) = split(client.set_keepalive(Some(keepalive)));
+        let (client, probe) = split(client.unwrap().on_timer_expired(TcpTimer::Keepalive));
+        let client = client.unwrap();
+        assert_eq!(probe.len(), 1);
+        assert_eq!(client.keepalive_probes, 1);
+        let last_activity = client.last_activity;
+
+        let mut out_of_window = server.create_segment(
+            server.send_sequence.next + 1_000_000,
+            ControlBits::get_ack(),
+            b"spoofed".to_vec(),
+        );
+        out_of_window.acknowledgement_number = client.send_sequence.next;
+        let (client, _) = deliver(client, &[out_of_window]);
+        let client = client.unwrap();
+        assert_eq!(client.keepalive_probes, 1);
+        assert_eq!(client.last_activity, last_activity);
+
+        let (_, answer) = deliver(server, &probe);
+        let (client, _) = deliver(client, &answer);
+        let client = client.unwrap();
+        assert_eq!(client.keepalive_probes, 0);
+        assert!(client.last_activity > last_activity);
+    }
+
+    // Whether the segments are a single challenge ACK of the TCB, i.e. an ACK of RCV.NXT from SND.NXT.
+    fn is_challenge_ack(tcb: &TCB, segments: &[TCP]) -> bool {
+        match segments {
+            [ack] => {
+                TCB::is_pure_acknowledgement(ack)
+                    && ack.sequence_number == tcb.send_sequence.next
+                    && ack.acknowledgement_number == tcb.receive_sequence.next
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn in_window_reset_gets_challenge_ack() {
+        let (client, server) = establish();
+
+        let reset = server.create_segment(
+            client.receive_sequence.next + 10,
+            ControlBits::get_rst(),
+            vec![],
+        );
+        let (client, sent) = deliver(client, &[reset]);
+        let client = client.expect("an inexact RST does not reset the connection");
+        assert_eq!(client.state, TcpState::Established);
+        assert!(is_challenge_ack(&client, &sent));
+    }
+
+    #[test]
+    fn exact_reset_tears_connection_down() {
+        let (client, server) = establish();
+
+        let reset =
+            server.create_segment(client.receive_sequence.next, ControlBits::get_rst(), vec![]);
+        let (client, sent) = deliver(client, &[reset]);
+        assert!(client.is_none());
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn out_of_window_reset_is_dropped() {
+        let (client, server) = establish();
+
+        let reset = server.create_segment(
+            client.receive_sequence.next + (1 << 30),
+            ControlBits::get_rst(),
+            vec![],
+        );
+        let (client, sent) = deliver(client, &[reset]);
+        assert_eq!(client.unwrap().state, TcpState::Established);
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn syn_in_synchronized_state_gets_challenge_ack() {
+        let (client, server) = establish();
+
+        let syn =
+            server.create_segment(client.receive_sequence.next, ControlBits::get_syn(), vec![]);
+        let (client, sent) = deliver(client, &[syn]);
+        let client = client.expect("a SYN does not reset the connection");
+        assert_eq!(client.state, TcpState::Established);
+        assert!(is_challenge_ack(&client, &sent));
+    }
+
+    #[test]
+    fn listener_resets_stray_ack() {
+        let listener = TCB::default();
+        let (_, client) = establish();
+
+        let ack = client.create_segment(client.send_sequence.next, ControlBits::get_ack(), vec![]);
+        let (listener, sent) = deliver(listener, &[ack.clone()]);
+        assert!(listener.is_none());
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].control_bits.rst);
+        assert_eq!(sent[0].sequence_number, ack.acknowledgement_number);
+    }
+
+    #[test]
+    fn unacknowledged_data_times_out() {
+        let (client, _) = establish();
+        let (client, data) = split(client.write(b"hello").unwrap());
+        let mut client = client.unwrap();
+        assert_eq!(data.len(), 1);
+
+        for _ in 0..MAX_RETRANSMISSIONS {
+            let (new_client, retransmitted) =
+                split(client.on_timer_expired(TcpTimer::Retransmission));
+            client = new_client.expect("the connection is given up too early");
+            assert_eq!(retransmitted.len(), 1);
+            assert_eq!(retransmitted[0].data, b"hello");
+        }
+
+        assert!(matches!(
+            client.on_timer_expired(TcpTimer::Retransmission),
+            TCPStateChange::TimedOut
+        ));
+    }
+
+    // Replaces the timestamps the segment carries.
+    fn set_timestamps(segment: &mut TCP, new_value: u32, new_echo_reply: u32) {
+        for option in segment.options.iter_mut() {
+            if let TcpOption::Timestamps { value, echo_reply } = option {
+                *value = new_value;
+                *echo_reply = new_echo_reply;
+            }
+        }
+    }
+
+    #[test]
+    fn paws_rejects_old_timestamp() {
+        let (client, server) = establish();
+        let recent = client.timestamp_recent;
+
+        let mut data = server.create_segment(
+            server.send_sequence.next,
+            ControlBits::get_psh_ack(),
+            b"old".to_vec(),
+        );
+        set_timestamps(&mut data, recent.wrapping_sub(1), 0);
+        let (client, sent) = deliver(client, &[data]);
+        let client = client.unwrap();
+
+        assert!(client.receive_buffer.is_empty());
+        assert_eq!(client.timestamp_recent, recent);
+        assert!(is_challenge_ack(&client, &sent));
+    }
+
+    #[test]
+    fn paws_accepts_wrapped_timestamp() {
+        let (mut client, server) = establish();
+        client.timestamp_recent = u32::MAX - 10;
+
+        let mut data = server.create_segment(
+            server.send_sequence.next,
+            ControlBits::get_psh_ack(),
+            b"new".to_vec(),
+        );
+        set_timestamps(&mut data, 5, 0);
+        let (client, _) = deliver(client, &[data]);
+        let client = client.unwrap();
+
+        assert_eq!(client.receive_buffer, b"new");
+        assert_eq!(client.timestamp_recent, 5);
+    }
+
+    #[test]
+    fn ts_recent_is_only_taken_from_segments_up_to_last_ack_sent() {
+        let (client, server) = establish();
+        let recent = client.timestamp_recent;
+
+        // A segment beyond a gap lies past what we have acknowledged.
+        let mut out_of_order = server.create_segment(
+            server.send_sequence.next + 10,
+            ControlBits::get_psh_ack(),
+            b"later".to_vec(),
+        );
+        set_timestamps(&mut out_of_order, recent.wrapping_add(100), 0);
+        let (client, _) = deliver(client, &[out_of_order]);
+        let client = client.unwrap();
+        assert_eq!(client.timestamp_recent, recent);
+
+        let mut in_sequence = server.create_segment(
+            server.send_sequence.next,
+            ControlBits::get_psh_ack(),
+            b"first".to_vec(),
+        );
+        set_timestamps(&mut in_sequence, recent.wrapping_add(50), 0);
+        let (client, _) = deliver(client, &[in_sequence]);
+        assert_eq!(client.unwrap().timestamp_recent, recent.wrapping_add(50));
+    }
+
+    #[test]
+    fn echoed_timestamp_samples_round_trip_time() {
+        let (mut client, server) = establish();
+        client.retransmission_queue = RetransmissionQueue::default();
+        let (client, _) = split(client.write(b"hello").unwrap());
+        let client = client.unwrap();
+
+        // An ACK that acknowledges nothing new is not sampled.
+        let mut duplicate =
+            server.create_segment(server.send_sequence.next, ControlBits::get_ack(), vec![]);
+        set_timestamps(
+            &mut duplicate,
+            client.timestamp_recent,
+            TCB::timestamp_clock().wrapping_sub(50),
+        );
+        let (client, _) = deliver(client, &[duplicate.clone()]);
+        let client = client.unwrap();
+        assert_eq!(client.retransmission_queue.smoothed_round_trip_time, None);
+
+        let mut ack = duplicate;
+        ack.acknowledgement_number = client.send_sequence.next;
+        let (client, _) = deliver(client, &[ack]);
+        let smoothed = client
+            .unwrap()
+            .retransmission_queue
+            .smoothed_round_trip_time
+            .unwrap();
+        assert!(smoothed >= Duration::from_millis(50) && smoothed < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn rejected_segments_are_not_duplicate_acknowledgements() {
+        let (client, server) = establish();
+        let (client, _) = split(client.write(b"hello").unwrap());
+        let client = client.unwrap();
+
+        // The first ACK updates the window, which is not a duplicate yet.
+        let ack = server.create_segment(server.send_sequence.next, ControlBits::get_ack(), vec![]);
+        let (client, _) = deliver(client, &[ack.clone()]);
+        let client = client.unwrap();
+
+        let mut outdated = ack.clone();
+        set_timestamps(&mut outdated, client.timestamp_recent.wrapping_sub(1), 0);
+        let (client, _) = deliver(client, &[outdated.clone(), outdated.clone(), outdated]);
+        let client = client.unwrap();
+        assert_eq!(client.duplicate_acknowledgements, 0);
+
+        let (client, _) = deliver(client, &[ack]);
+        assert_eq!(client.unwrap().duplicate_acknowledgements, 1);
+    }
+
+    #[test]
+    fn duplicate_acknowledgements_trigger_fast_retransmit_and_recovery() {
+        let (client, server) = establish();
+        let mss = client.send_maximum_segment_size as usize;
+        let (client, data) = split(client.write(&vec![7; 3 * mss]).unwrap());
+        let client = client.unwrap();
+        assert_eq!(data.len(), 3);
+
+        // The first ACK updates the window, the DupThresh following it are duplicates.
+        let ack = server.create_segment(server.send_sequence.next, ControlBits::get_ack(), vec![]);
+        let (client, sent) = deliver(client, &vec![ack.clone(); 3]);
+        let client = client.unwrap();
+        assert!(!client.fast_recovery);
+        assert!(sent.is_empty());
+
+        let (client, sent) = deliver(client, &[ack.clone()]);
+        let client = client.unwrap();
+        assert!(client.fast_recovery);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].sequence_number, data[0].sequence_number);
+        let flight_size = 3 * mss as u32;
+        assert_eq!(
+            client.congestion_control.slow_start_threshold(),
+            (flight_size / 2).max(2 * mss as u32)
+        );
+
+        // A partial acknowledgement retransmits the next segment right away.
+        let mut partial = ack.clone();
+        partial.acknowledgement_number = data[1].sequence_number;
+        let (client, sent) = deliver(client, &[partial]);
+        let client = client.unwrap();
+        assert!(client.fast_recovery);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].sequence_number, data[1].sequence_number);
+
+        let mut full = ack;
+        full.acknowledgement_number = client.send_sequence.next;
+        let (client, _) = deliver(client, &[full]);
+        let client = client.unwrap();
+        assert!(!client.fast_recovery);
+        assert_eq!(
+            client.congestion_control.congestion_window(),
+            client
+                .congestion_control
+                .slow_start_threshold()
+                .min(2 * mss as u32)
+        );
+    }
+
+    #[test]
+    fn congestion_control_is_selected_per_connection() {
+        let (client, server) = establish();
+        let (client, _) = split(client.set_congestion_control(CongestionControlAlgorithm::Cubic));
+        let client = client.unwrap();
+
+        assert_eq!(
+            client.congestion_control.algorithm(),
+            CongestionControlAlgorithm::Cubic
+        );
+        assert_eq!(
+            server.congestion_control.algorithm(),
+            CongestionControlAlgorithm::NewReno
+        );
+        assert_eq!(
+            client.congestion_control.congestion_window(),
+            CongestionControlAlgorithm::Cubic
+                .create(client.send_maximum_segment_size)
+                .congestion_window()
+        );
+    }
+
+    #[test]
+    fn acknowledgement_of_a_single_segment_is_delayed_until_the_timer_expires() {
+        let (client, server) = establish();
+        let mss = server.send_maximum_segment_size as usize;
+        let (_, data) = split(server.write(&vec![1; mss]).unwrap());
+        assert_eq!(data.len(), 1);
+
+        let (client, sent) = deliver(client, &data);
+        let client = client.unwrap();
+        assert!(sent.is_empty());
+        assert!(client.delayed_acknowledgement_expiry.is_some());
+
+        let (client, sent) = split(client.on_timer_expired(TcpTimer::DelayedAcknowledgement));
+        let client = client.unwrap();
+        assert!(client.delayed_acknowledgement_expiry.is_none());
+        assert!(is_challenge_ack(&client, &sent));
+    }
+
+    #[test]
+    fn every_second_full_segment_is_acknowledged_right_away() {
+        let (client, server) = establish();
+        let mss = server.send_maximum_segment_size as usize;
+        let (_, data) = split(server.write(&vec![1; 2 * mss]).unwrap());
+        assert_eq!(data.len(), 2);
+
+        let (client, sent) = deliver(client, &data[..1]);
+        assert!(sent.is_empty());
+
+        let (client, sent) = deliver(client.unwrap(), &data[1..]);
+        let client = client.unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].acknowledgement_number,
+            data[1].sequence_number + data[1].data.len() as u32
+        );
+        assert!(client.delayed_acknowledgement_expiry.is_none());
+        assert_eq!(client.unacknowledged_received, 0);
+    }
+
+    #[test]
+    fn nagle_holds_back_small_segments_while_data_is_in_flight() {
+        let (client, _) = establish();
+        let (client, sent) = split(client.write(b"a").unwrap());
+        assert_eq!(sent.len(), 1);
+
+        let (client, sent) = split(client.unwrap().write(b"b").unwrap());
+        let client = client.unwrap();
+        assert!(sent.is_empty());
+
+        // Disabling Nagle's algorithm sends what it held back.
+        let (client, sent) = split(client.set_no_delay(true));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data, b"b");
+
+        let (_, sent) = split(client.unwrap().write(b"c").unwrap());
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data, b"c");
+    }
+
+    #[test]
+    fn window_is_held_back_until_it_opens_by_a_segment() {
+        let (mut client, _) = establish();
+        let capacity = TCB::receive_buffer_capacity(client.receive_window_shift);
+        let mss = client.receive_maximum_segment_size as usize;
+        assert!(mss < capacity as usize / 2);
+
+        // The application has not read anything the peer sent, which closed the window.
+        client.receive_buffer = vec![0; capacity as usize];
+        client.receive_sequence.window = 0;
+
+        let (data, state_change) = client.read(mss - 1).unwrap();
+        assert_eq!(data.len(), mss - 1);
+        let (client, sent) = split(state_change);
+        let client = client.unwrap();
+        assert!(sent.is_empty());
+        assert_eq!(client.receive_sequence.window, 0);
+
+        let (_, state_change) = client.read(1).unwrap();
+        let (client, sent) = split(state_change);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(client.unwrap().receive_sequence.window, mss as u32);
+    }
+
+    #[test]
+    fn closed_window_is_probed_until_it_opens() {
+        let (client, server) = establish();
+        let mut zero_window =
+            server.create_segment(server.send_sequence.next, ControlBits::get_ack(), vec![]);
+        zero_window.window = 0;
+        let (client, _) = deliver(client, &[zero_window]);
+
+        let (client, sent) = split(client.unwrap().write(b"data").unwrap());
+        let client = client.unwrap();
+        assert!(sent.is_empty());
+        assert!(client.persist_expiry.is_some());
+        let first_timeout = client.persist_timeout();
+
+        let (client, probe) = split(client.on_timer_expired(TcpTimer::Persist));
+        let client = client.unwrap();
+        assert_eq!(probe.len(), 1);
+        assert_eq!(
+            probe[0].sequence_number,
+            client.send_sequence.unacknowledged - 1
+        );
+        assert!(probe[0].data.is_empty());
+        assert_eq!(client.window_probes, 1);
+        assert_eq!(client.persist_timeout(), first_timeout * 2);
+
+        // The peer answers the probe with an acknowledgement announcing that its window has opened.
+        let (server, answer) = deliver(server, &probe);
+        assert!(server.unwrap().receive_sequence.window > 0);
+        let (client, sent) = deliver(client, &answer);
+        let client = client.unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data, b"data");
+        assert_eq!(client.persist_expiry, None);
+        assert_eq!(client.window_probes, 0);
+    }
+
+    #[test]
+    fn urgent_data_is_read_out_of_band_at_the_mark() {
+        let (client, server) = establish();
+        let (server, _) = split(server.set_no_delay(true));
+        let server = server.unwrap();
+        let start = server.send_sequence.next;
+
+        let (server, mut sent) = split(server.write(b"ab").unwrap());
+        let (server, urgent) = split(server.unwrap().write_urgent(b"cd").unwrap());
+        let (_, after) = split(server.unwrap().write(b"ef").unwrap());
+
+        // The urgent pointer points to the byte following the urgent data.
+        assert_eq!(urgent.len(), 1);
+        assert!(urgent[0].control_bits.urg);
+        assert_eq!(urgent[0].urgent_pointer_sequence(), Some(start + 4));
+        assert!(!after[0].control_bits.urg);
+        sent.extend(urgent);
+        sent.extend(after);
+
+        let (client, _) = deliver(client, &sent);
+        let client = client.unwrap();
+        assert!(client.is_urgent_pending());
+
+        // Reading stops at the mark, which the urgent byte was taken out of the stream at.
+        let (data, state_change) = client.read(10).unwrap();
+        assert_eq!(data, b"abc");
+        let (client, _) = split(state_change);
+        let client = client.unwrap();
+        assert!(client.is_at_urgent_mark());
+
+        let (urgent_data, state_change) = client.read_urgent().unwrap();
+        assert_eq!(urgent_data, Some(b'd'));
+        let (client, _) = split(state_change);
+        let client = client.unwrap();
+        assert!(!client.is_urgent_pending());
+
+        let (data, _) = client.read(10).unwrap();
+        assert_eq!(data, b"ef");
+    }
 }
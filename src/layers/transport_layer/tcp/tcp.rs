@@ -8,6 +8,8 @@ use crate::common::proto::Proto;
 use crate::layers::ip_layer::ip_protocol::Protocol;
 use crate::layers::ip_layer::IPAddress;
 use crate::layers::transport_layer::tcp::control_bits::ControlBits;
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+use crate::layers::transport_layer::tcp::tcp_option::TcpOption;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
@@ -15,15 +17,15 @@ use std::fmt::{Display, Formatter};
 pub struct TCP {
     pub src_port: u16,
     pub dst_port: u16,
-    pub sequence_number: u32,
-    pub acknowledgement_number: u32,
+    pub sequence_number: SequenceNumber,
+    pub acknowledgement_number: SequenceNumber,
     pub data_offset: U4,
     pub reserved: U6,
     pub control_bits: ControlBits,
     pub window: u16,
     pub checksum: u16,
     pub urgent_pointer: u16,
-    pub options: Vec<u8>,
+    pub options: Vec<TcpOption>,
     pub data: Vec<u8>,
 }
 
@@ -48,8 +50,8 @@ impl Proto for TCP {
         Ok(TCP {
             src_port: read_u16(buf).wrap_err("reading source port")?,
             dst_port: read_u16(buf).wrap_err("reading destination port")?,
-            sequence_number: read_u32(buf).wrap_err("reading sequence number")?,
-            acknowledgement_number: read_u32(buf).wrap_err("ack number")?,
+            sequence_number: SequenceNumber(read_u32(buf).wrap_err("reading sequence number")?),
+            acknowledgement_number: SequenceNumber(read_u32(buf).wrap_err("ack number")?),
             data_offset: {
                 offset_reserved_control_bits =
                     read_u16(buf).wrap_err("reading offset reserved control bits")?;
@@ -68,8 +70,12 @@ impl Proto for TCP {
             window: read_u16(buf).wrap_err("reading window")?,
             checksum: read_u16(buf).wrap_err("reading checksum")?,
             urgent_pointer: read_u16(buf).wrap_err("reading urgent pointer")?,
-            options: read_vec(buf, (data_offset - TCP_MIN_HEADER_LENGTH) as usize)
-                .wrap_err("reading options")?,
+            options: {
+                // The data offset is measured in 32-bit words.
+                let options_length = (data_offset - TCP_MIN_HEADER_LENGTH) as usize * 4;
+                let options = read_vec(buf, options_length).wrap_err("reading options")?;
+                TcpOption::parse_options(&mut options.as_slice()).wrap_err("parsing options")?
+            },
             data: buf.to_vec(),
         })
     }
@@ -80,8 +86,8 @@ impl TCP {
         let mut bytes: Vec<u8> = Vec::new();
         bytes.extend_from_slice(&self.src_port.to_be_bytes());
         bytes.extend_from_slice(&self.dst_port.to_be_bytes());
-        bytes.extend_from_slice(&self.sequence_number.to_be_bytes());
-        bytes.extend_from_slice(&self.acknowledgement_number.to_be_bytes());
+        bytes.extend_from_slice(&self.sequence_number.0.to_be_bytes());
+        bytes.extend_from_slice(&self.acknowledgement_number.0.to_be_bytes());
         bytes.extend_from_slice(
             &(0 | ((self.data_offset as u16) << 12) | (self.control_bits.serialize() as u16))
                 .to_be_bytes(),
@@ -89,20 +95,40 @@ impl TCP {
         bytes.extend_from_slice(&self.window.to_be_bytes());
         bytes.extend_from_slice(&self.calculate_checksum(src_adr, dst_adr)?.to_be_bytes());
         bytes.extend_from_slice(&self.urgent_pointer.to_be_bytes());
-        // TODO: Add options / padding
-        bytes.extend_from_slice(&self.options);
+        bytes.extend_from_slice(&TcpOption::serialize_options(&self.options));
 
         bytes.extend_from_slice(self.data.as_slice());
 
         Ok(bytes)
     }
 
+    // The amount of sequence space occupied by this segment (SEG.LEN), i.e. the data as well as any SYN or FIN.
+    pub fn segment_length(&self) -> u32 {
+        self.data.len() as u32 + self.control_bits.syn as u32 + self.control_bits.fin as u32
+    }
+
+    // The sequence number following the urgent data (SEG.SEQ + SEG.UP), None if the segment points to no urgent data.
+    // The urgent pointer is only significant with the URG bit set, and a pointer of 0 points to no data of the segment.
+    // As specified in https://datatracker.ietf.org/doc/html/rfc9293#section-3.8.5
+    pub fn urgent_pointer_sequence(&self) -> Option<SequenceNumber> {
+        if self.control_bits.urg && self.urgent_pointer != 0 {
+            Some(self.sequence_number + self.urgent_pointer as u32)
+        } else {
+            None
+        }
+    }
+
+    // The data offset of a segment with the given options, i.e. the length of its header in 32-bit words.
+    pub fn calculate_data_offset(options: &[TcpOption]) -> U4 {
+        TCP_MIN_HEADER_LENGTH + (TcpOption::serialize_options(options).len() / 4) as U4
+    }
+
     // Calculates the full length of this TCP packet, i.e. Header + Data
     pub fn len(&self) -> eyre::Result<u16> {
+        // The header length includes the options & padding.
         let header_len = (self.data_offset as u16)
             .checked_mul(4) // Convert no 32bit words to no bytes.
-            .wrap_err("header len is too large")?
-            + (self.options.len() as u16);
+            .wrap_err("header len is too large")?;
 
         let data_len = self.data.len() as u16;
 
@@ -134,10 +160,10 @@ impl TCP {
         // Actual data for checksum
         num.push(self.src_port);
         num.push(self.dst_port);
-        num.push((self.sequence_number >> 16) as u16);
-        num.push(self.sequence_number as u16);
-        num.push((self.acknowledgement_number >> 16) as u16);
-        num.push(self.acknowledgement_number as u16);
+        num.push((self.sequence_number.0 >> 16) as u16);
+        num.push(self.sequence_number.0 as u16);
+        num.push((self.acknowledgement_number.0 >> 16) as u16);
+        num.push(self.acknowledgement_number.0 as u16);
         num.push(
             ((self.data_offset as u16) << 12)
                 | (self.reserved << 6) as u16
@@ -146,7 +172,11 @@ impl TCP {
         num.push(self.window);
         num.push(0 as u16);
         num.push(self.urgent_pointer);
-        // TODO: Support options/padding
+
+        // The options are padded to 32 bits so they always form whole 16-bit words.
+        for word in TcpOption::serialize_options(&self.options).chunks_exact(2) {
+            num.push(u16::from_be_bytes([word[0], word[1]]));
+        }
 
         for (index, val) in self.data.iter().enumerate() {
             if index % 2 == 0 {
@@ -177,7 +207,7 @@ impl Display for TCP {
     Control Bits: {},
     Window: {},
     Urgent pointer: {},
-    Options: {:?},
+    Options: [{}],
     Data: {:?},
 }}",
             self.src_port,
@@ -189,7 +219,11 @@ impl Display for TCP {
             indent_string(self.control_bits.to_string()),
             self.window,
             self.urgent_pointer,
-            self.options,
+            self.options
+                .iter()
+                .map(|option| option.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
             self.data,
         )
     }
@@ -1,5 +1,7 @@
 use crate::layers::ip_layer::IPAddress;
 
+// Identifies a connection as seen from incoming segments,
+// i.e. src is the remote end of the connection and dst is our end.
 #[derive(Clone, Debug, Hash)]
 pub struct TCPQuad {
     pub src_ip: IPAddress,
@@ -0,0 +1,279 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use eyre::{Context, ContextCompat};
+
+use crate::common::parsing::{read_u16, read_u32, read_u8};
+use crate::layers::transport_layer::tcp::sequence_number::SequenceNumber;
+
+const END_OF_OPTION_LIST_KIND: u8 = 0;
+const NO_OPERATION_KIND: u8 = 1;
+const MAXIMUM_SEGMENT_SIZE_KIND: u8 = 2;
+const WINDOW_SCALE_KIND: u8 = 3;
+const SACK_PERMITTED_KIND: u8 = 4;
+const SACK_KIND: u8 = 5;
+const TIMESTAMPS_KIND: u8 = 8;
+
+// A block of data the peer has received beyond RCV.NXT, from left up to (excluding) right.
+// As specified in https://datatracker.ietf.org/doc/html/rfc2018#section-3
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SackBlock {
+    pub left: SequenceNumber,
+    pub right: SequenceNumber,
+}
+
+// A TCP option as determined by https://www.iana.org/assignments/tcp-parameters/tcp-parameters.xhtml
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TcpOption {
+    EndOfOptionList,
+    NoOperation,
+    MaximumSegmentSize(u16),
+    WindowScale(u8),
+    SackPermitted,
+    Sack(Vec<SackBlock>),
+    Timestamps { value: u32, echo_reply: u32 },
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+impl TcpOption {
+    // Parses the options of a segment, ignoring everything after the end of option list.
+    pub fn parse_options(buf: &mut &[u8]) -> eyre::Result<Vec<TcpOption>> {
+        let mut options = vec![];
+
+        while !buf.is_empty() {
+            match TcpOption::parse(buf).wrap_err("reading option")? {
+                TcpOption::EndOfOptionList => break,
+                option => options.push(option),
+            }
+        }
+
+        Ok(options)
+    }
+
+    pub fn parse(buf: &mut &[u8]) -> eyre::Result<TcpOption> {
+        let kind = read_u8(buf).wrap_err("reading kind")?;
+        match kind {
+            END_OF_OPTION_LIST_KIND => return Ok(TcpOption::EndOfOptionList),
+            NO_OPERATION_KIND => return Ok(TcpOption::NoOperation),
+            _ => {}
+        }
+
+        // The length includes the kind & length bytes themselves.
+        let length = read_u8(buf).wrap_err("reading length")?;
+        if length < 2 || length as usize - 2 > buf.len() {
+            eyre::bail!("Invalid length {} for option of kind {}", length, kind);
+        }
+
+        let mut data = &buf[..length as usize - 2];
+        *buf = &buf[length as usize - 2..];
+
+        let option = match (kind, length) {
+            (MAXIMUM_SEGMENT_SIZE_KIND, 4) => TcpOption::MaximumSegmentSize(
+                read_u16(&mut data).wrap_err("reading maximum segment size")?,
+            ),
+            (WINDOW_SCALE_KIND, 3) => {
+                TcpOption::WindowScale(read_u8(&mut data).wrap_err("reading window scale")?)
+            }
+            (SACK_PERMITTED_KIND, 2) => TcpOption::SackPermitted,
+            (SACK_KIND, _) if (length - 2).is_multiple_of(8) => {
+                let mut blocks = vec![];
+                while !data.is_empty() {
+                    blocks.push(SackBlock {
+                        left: SequenceNumber(read_u32(&mut data).wrap_err("reading left edge")?),
+                        right: SequenceNumber(read_u32(&mut data).wrap_err("reading right edge")?),
+                    });
+                }
+                TcpOption::Sack(blocks)
+            }
+            (TIMESTAMPS_KIND, 10) => TcpOption::Timestamps {
+                value: read_u32(&mut data).wrap_err("reading timestamp value")?,
+                echo_reply: read_u32(&mut data).wrap_err("reading timestamp echo reply")?,
+            },
+            (MAXIMUM_SEGMENT_SIZE_KIND, _)
+            | (WINDOW_SCALE_KIND, _)
+            | (SACK_PERMITTED_KIND, _)
+            | (SACK_KIND, _)
+            | (TIMESTAMPS_KIND, _) => {
+                eyre::bail!("Invalid length {} for option of kind {}", length, kind)
+            }
+            _ => TcpOption::Unknown {
+                kind,
+                data: data.to_vec(),
+            },
+        };
+
+        Ok(option)
+    }
+
+    // Serializes the options of a segment, padded to a multiple of 32 bits.
+    pub fn serialize_options(options: &[TcpOption]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = options.iter().flat_map(TcpOption::serialize).collect();
+
+        // The padding is made up of zeros, i.e. end of option list.
+        while !bytes.len().is_multiple_of(4) {
+            bytes.push(END_OF_OPTION_LIST_KIND);
+        }
+
+        bytes
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let (kind, data) = match self {
+            TcpOption::EndOfOptionList => return vec![END_OF_OPTION_LIST_KIND],
+            TcpOption::NoOperation => return vec![NO_OPERATION_KIND],
+            TcpOption::MaximumSegmentSize(size) => {
+                (MAXIMUM_SEGMENT_SIZE_KIND, size.to_be_bytes().to_vec())
+            }
+            TcpOption::WindowScale(shift) => (WINDOW_SCALE_KIND, vec![*shift]),
+            TcpOption::SackPermitted => (SACK_PERMITTED_KIND, vec![]),
+            TcpOption::Sack(blocks) => (
+                SACK_KIND,
+                blocks
+                    .iter()
+                    .flat_map(|block| [block.left.0.to_be_bytes(), block.right.0.to_be_bytes()])
+                    .flatten()
+                    .collect(),
+            ),
+            TcpOption::Timestamps { value, echo_reply } => (
+                TIMESTAMPS_KIND,
+                [value.to_be_bytes(), echo_reply.to_be_bytes()].concat(),
+            ),
+            TcpOption::Unknown { kind, data } => (*kind, data.clone()),
+        };
+
+        let mut bytes = vec![kind, data.len() as u8 + 2];
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+}
+
+impl Display for TcpOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpOption::EndOfOptionList => write!(f, "EOL"),
+            TcpOption::NoOperation => write!(f, "NOP"),
+            TcpOption::MaximumSegmentSize(size) => write!(f, "MSS {}", size),
+            TcpOption::WindowScale(shift) => write!(f, "WS {}", shift),
+            TcpOption::SackPermitted => write!(f, "SACK_PERM"),
+            TcpOption::Sack(blocks) => {
+                write!(f, "SACK")?;
+                for block in blocks {
+                    write!(f, " {}-{}", block.left, block.right)?;
+                }
+                Ok(())
+            }
+            TcpOption::Timestamps { value, echo_reply } => {
+                write!(f, "TS val {} ecr {}", value, echo_reply)
+            }
+            TcpOption::Unknown { kind, data } => write!(f, "Unknown ({}) {:?}", kind, data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(bytes: &[u8]) -> eyre::Result<Vec<TcpOption>> {
+        TcpOption::parse_options(&mut &bytes[..])
+    }
+
+    #[test]
+    fn round_trip() {
+        let options = vec![
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::NoOperation,
+            TcpOption::WindowScale(7),
+            TcpOption::SackPermitted,
+            TcpOption::Sack(vec![
+                SackBlock {
+                    left: SequenceNumber(u32::MAX - 10),
+                    right: SequenceNumber(20),
+                },
+                SackBlock {
+                    left: SequenceNumber(100),
+                    right: SequenceNumber(200),
+                },
+            ]),
+            TcpOption::Timestamps {
+                value: 0xdead_beef,
+                echo_reply: 42,
+            },
+            TcpOption::Unknown {
+                kind: 30,
+                data: vec![1, 2, 3],
+            },
+        ];
+
+        let bytes = TcpOption::serialize_options(&options);
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(parse_all(&bytes).unwrap(), options);
+    }
+
+    #[test]
+    fn padding() {
+        // The end of option list pads the options to 32 bits, everything after it is ignored.
+        let bytes = TcpOption::serialize_options(&[TcpOption::WindowScale(3)]);
+        assert_eq!(
+            bytes,
+            vec![WINDOW_SCALE_KIND, 3, 3, END_OF_OPTION_LIST_KIND]
+        );
+        assert_eq!(
+            parse_all(&[WINDOW_SCALE_KIND, 3, 3, 0, 2, 4, 5, 180]).unwrap(),
+            vec![TcpOption::WindowScale(3)]
+        );
+
+        // No operation only aligns the next option.
+        assert_eq!(
+            parse_all(&[1, 1, SACK_PERMITTED_KIND, 2]).unwrap(),
+            vec![
+                TcpOption::NoOperation,
+                TcpOption::NoOperation,
+                TcpOption::SackPermitted
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_kinds() {
+        assert_eq!(
+            parse_all(&[254, 4, 0xab, 0xcd, 1]).unwrap(),
+            vec![
+                TcpOption::Unknown {
+                    kind: 254,
+                    data: vec![0xab, 0xcd],
+                },
+                TcpOption::NoOperation
+            ]
+        );
+        assert_eq!(
+            parse_all(&[254, 2]).unwrap(),
+            vec![TcpOption::Unknown {
+                kind: 254,
+                data: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn truncated_options() {
+        // Missing the length.
+        assert!(parse_all(&[MAXIMUM_SEGMENT_SIZE_KIND]).is_err());
+        // The length runs past the end of the options.
+        assert!(parse_all(&[MAXIMUM_SEGMENT_SIZE_KIND, 4, 5]).is_err());
+        assert!(parse_all(&[TIMESTAMPS_KIND, 10, 0, 0, 0, 1, 0, 0]).is_err());
+        assert!(parse_all(&[254, 10, 1]).is_err());
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        // A length below 2 would not even cover the kind & length bytes.
+        assert!(parse_all(&[MAXIMUM_SEGMENT_SIZE_KIND, 0, 0, 0]).is_err());
+        assert!(parse_all(&[254, 1, 0, 0]).is_err());
+        // Known kinds have a fixed length.
+        assert!(parse_all(&[MAXIMUM_SEGMENT_SIZE_KIND, 3, 5]).is_err());
+        assert!(parse_all(&[WINDOW_SCALE_KIND, 4, 7, 0]).is_err());
+        assert!(parse_all(&[SACK_PERMITTED_KIND, 3, 0]).is_err());
+        assert!(parse_all(&[SACK_KIND, 6, 0, 0, 0, 1]).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// The timers that can be running for a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TcpTimer {
+    Retransmission,
+    TimeWait,
+    DelayedAcknowledgement,
+    Persist,
+    Keepalive,
+}
+
+impl TcpTimer {
+    pub const ALL: [TcpTimer; 5] = [
+        TcpTimer::Retransmission,
+        TcpTimer::TimeWait,
+        TcpTimer::DelayedAcknowledgement,
+        TcpTimer::Persist,
+        TcpTimer::Keepalive,
+    ];
+}
+
+impl Display for TcpTimer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpTimer::Retransmission => write!(f, "RETRANSMISSION"),
+            TcpTimer::TimeWait => write!(f, "TIME_WAIT"),
+            TcpTimer::DelayedAcknowledgement => write!(f, "DELAYED_ACK"),
+            TcpTimer::Persist => write!(f, "PERSIST"),
+            TcpTimer::Keepalive => write!(f, "KEEPALIVE"),
+        }
+    }
+}
@@ -39,7 +39,7 @@ impl Proto for TunLayer {
         let t = TunLayer {
             flags: read_u16(buf).wrap_err("reading flags")?,
             proto: Protocol::parse(read_u16(buf).wrap_err("reading protocol")?),
-            data: IPLayerProtocol::parse(buf),
+            data: IPLayerProtocol::parse(buf).wrap_err("parsing ip layer")?,
         };
 
         match (&t.proto, &t.data) {
@@ -0,0 +1,5 @@
+// Async versions of the sockets for tokio, which register the waker of the polling task with the stack
+// instead of blocking, so the stack thread wakes the task once the connection or port changes.
+pub mod tcp_listener;
+pub mod tcp_stream;
+pub mod udp_socket;
@@ -0,0 +1,63 @@
+use std::future::poll_fn;
+use std::io;
+use std::net::SocketAddr;
+use std::task::Poll;
+
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use crate::layers::transport_layer::tcp::keepalive::Keepalive;
+use crate::stack::async_io::tcp_stream::TcpStream;
+use crate::stack::{tcp_listener, to_io_error, Stack};
+
+// A socket accepting the connections peers open to an address & port of ours, like tokio::net::TcpListener.
+pub struct TcpListener {
+    inner: tcp_listener::TcpListener,
+}
+
+impl TcpListener {
+    // Starts accepting connections to the port on the address, any of our addresses if it is None.
+    pub fn bind(stack: &Stack, ip: Option<IPAddress>, port: u16) -> io::Result<TcpListener> {
+        Ok(TcpListener {
+            inner: tcp_listener::TcpListener::bind(stack, ip, port)?,
+        })
+    }
+
+    // Completes once a peer has established a connection, returning it together with the address & port of the peer.
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let inner = &self.inner;
+        let (stream, peer) = poll_fn(|cx| -> Poll<io::Result<_>> {
+            let mut shared = inner.stack().lock();
+            match inner.try_accept(&mut shared)? {
+                Some(accepted) => Poll::Ready(Ok(accepted)),
+                None => {
+                    shared
+                        .connections
+                        .register_accept_waker(inner.address(), cx.waker().clone())
+                        .map_err(to_io_error)?;
+                    Poll::Pending
+                }
+            }
+        })
+        .await?;
+
+        Ok((TcpStream::new(stream), peer))
+    }
+
+    // The address & port the listener accepts connections on, the unspecified address if it accepts them on any.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    // Enables keepalive with the given settings for the connections accepted from now on, or disables it with None.
+    pub fn set_keepalive(&self, keepalive: Option<Keepalive>) -> io::Result<()> {
+        self.inner.set_keepalive(keepalive)
+    }
+
+    // Sets the congestion control of the connections accepted from now on.
+    pub fn set_congestion_control(
+        &self,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> io::Result<()> {
+        self.inner.set_congestion_control(congestion_control)
+    }
+}
@@ -0,0 +1,149 @@
+use std::future::poll_fn;
+use std::io;
+use std::net::{Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use crate::layers::transport_layer::tcp::keepalive::Keepalive;
+use crate::stack::tcp_stream;
+use crate::stack::Stack;
+
+// A TCP connection between our end and a remote end, like tokio::net::TcpStream.
+pub struct TcpStream {
+    inner: tcp_stream::TcpStream,
+}
+
+impl TcpStream {
+    // Opens a connection to the port of the remote address, completing once it is established.
+    pub async fn connect(
+        stack: &Stack,
+        remote_ip: IPAddress,
+        remote_port: u16,
+    ) -> io::Result<TcpStream> {
+        let inner = tcp_stream::TcpStream::open(stack, &mut stack.lock(), remote_ip, remote_port)?;
+
+        poll_fn(|cx| {
+            let mut shared = inner.stack().lock();
+            match inner.try_connected(&shared) {
+                Some(result) => Poll::Ready(result),
+                None => {
+                    // The state changing from SYN-SENT wakes the writers up.
+                    shared
+                        .connections
+                        .register_write_waker(inner.quad(), cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await?;
+
+        Ok(TcpStream { inner })
+    }
+
+    pub(crate) fn new(inner: tcp_stream::TcpStream) -> TcpStream {
+        TcpStream { inner }
+    }
+
+    // The address & port of the remote end of the connection.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    // The address & port of our end of the connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    // Disables or re-enables Nagle's algorithm for the connection (TCP_NODELAY).
+    pub fn set_nodelay(&self, no_delay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(no_delay)
+    }
+
+    // Enables keepalive for the connection with the given settings (SO_KEEPALIVE), or disables it with None.
+    pub fn set_keepalive(&self, keepalive: Option<Keepalive>) -> io::Result<()> {
+        self.inner.set_keepalive(keepalive)
+    }
+
+    // Switches the connection to the given congestion control (TCP_CONGESTION).
+    pub fn set_congestion_control(
+        &self,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> io::Result<()> {
+        self.inner.set_congestion_control(congestion_control)
+    }
+
+    // Sends the data as urgent data (MSG_OOB), of which the peer reads the last byte out of band.
+    pub fn send_urgent(&self, data: &[u8]) -> io::Result<()> {
+        self.inner.send_urgent(data)
+    }
+
+    // Takes the byte of urgent data the peer has sent out of band (MSG_OOB), None if there is none.
+    pub fn recv_urgent(&self) -> io::Result<Option<u8>> {
+        self.inner.recv_urgent()
+    }
+
+    // Whether the next byte to read follows the urgent data the peer has sent (SIOCATMARK).
+    pub fn at_urgent_mark(&self) -> io::Result<bool> {
+        self.inner.at_urgent_mark()
+    }
+}
+
+impl AsyncRead for TcpStream {
+    // Completes once the peer has sent data, reading nothing once it has closed its side of the connection.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let inner = &self.inner;
+        let mut shared = inner.stack().lock();
+        match inner.try_read(&mut shared, buf.initialize_unfilled()) {
+            Some(Ok(n_bytes)) => {
+                buf.advance(n_bytes);
+                Poll::Ready(Ok(()))
+            }
+            Some(Err(err)) => Poll::Ready(Err(err)),
+            None => {
+                shared
+                    .connections
+                    .register_read_waker(inner.quad(), cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    // Completes once the connection has room to buffer some of the data, returning how much of it was written.
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let inner = &self.inner;
+        let mut shared = inner.stack().lock();
+        match inner.try_write(&mut shared, buf) {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared
+                    .connections
+                    .register_write_waker(inner.quad(), cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    // The data is sent as soon as the windows allow, there is nothing to flush.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // Shuts down writing, sending our FIN once everything written before has been sent.
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.inner.shutdown(Shutdown::Write))
+    }
+}
@@ -0,0 +1,80 @@
+use std::future::poll_fn;
+use std::io;
+use std::task::Poll;
+
+use crate::layers::ip_layer::IPAddress;
+use crate::stack::{to_io_error, Stack};
+
+// A socket sending & receiving datagrams on a UDP port of ours, like tokio::net::UdpSocket.
+pub struct UdpSocket {
+    stack: Stack,
+    port: u16,
+}
+
+impl UdpSocket {
+    // Starts receiving the datagrams sent to the port on any of our addresses.
+    pub fn bind(stack: &Stack, port: u16) -> io::Result<UdpSocket> {
+        let mut shared = stack.lock();
+        shared.check_running()?;
+        shared
+            .udp_sockets
+            .bind(port)
+            .map_err(|err| io::Error::new(io::ErrorKind::AddrInUse, format!("{:#}", err)))?;
+
+        Ok(UdpSocket {
+            stack: stack.clone(),
+            port,
+        })
+    }
+
+    // Sends the data as a single datagram to the port of the remote address, returning how much was sent.
+    // Datagrams are not buffered, so this never has to wait.
+    pub async fn send_to(
+        &self,
+        buf: &[u8],
+        remote_ip: IPAddress,
+        remote_port: u16,
+    ) -> io::Result<usize> {
+        self.stack
+            .send_datagram(self.port, remote_ip, remote_port, buf.to_vec())?;
+        Ok(buf.len())
+    }
+
+    // Completes once a datagram arrives, returning its length together with the address & port it came from.
+    // A datagram longer than the buffer is cut off, the rest of it is dropped.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, (IPAddress, u16))> {
+        poll_fn(|cx| {
+            let mut shared = self.stack.lock();
+            shared.check_running()?;
+
+            let datagram = shared.udp_sockets.receive(self.port).map_err(to_io_error)?;
+            match datagram {
+                Some(datagram) => {
+                    let length = datagram.data.len().min(buf.len());
+                    buf[..length].copy_from_slice(&datagram.data[..length]);
+                    Poll::Ready(Ok((length, datagram.source)))
+                }
+                None => {
+                    shared
+                        .udp_sockets
+                        .register_waker(self.port, cx.waker().clone())
+                        .map_err(to_io_error)?;
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    // The port the socket is bound to.
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for UdpSocket {
+    // Stops receiving on the port, dropping the datagrams nobody has received.
+    fn drop(&mut self) {
+        self.stack.lock().udp_sockets.unbind(self.port);
+    }
+}
@@ -0,0 +1,526 @@
+use std::convert::TryFrom;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use eyre::Context;
+use log::{debug, trace};
+use tun_tap::Iface;
+
+use crate::common::proto::Proto;
+use crate::layers::ip_layer::ip_layer::IPLayerProtocol;
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use crate::layers::transport_layer::tcp::connection_table::ConnectionTable;
+use crate::layers::transport_layer::tcp::tcp::TCP;
+use crate::layers::transport_layer::tcp::tcp_ip_port_quad::TCPQuad;
+use crate::layers::transport_layer::transport_layer::TransportLayer;
+#[cfg(feature = "async")]
+use crate::layers::transport_layer::udp::udp::UDP;
+use crate::layers::tun_layer::tun_layer::TunLayer;
+use crate::stack::udp_sockets::UdpSockets;
+
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod tcp_listener;
+pub mod tcp_stream;
+// Only the async sockets receive datagrams, without them every datagram is dropped.
+#[cfg_attr(not(feature = "async"), allow(dead_code))]
+mod udp_sockets;
+
+// The ports we pick the local port of the connections we open from, as suggested in
+// https://datatracker.ietf.org/doc/html/rfc6335#section-6
+const EPHEMERAL_PORTS: std::ops::RangeInclusive<u16> = 49152..=65535;
+
+// The network stack running on a tun interface, which the sockets of the application are opened on.
+// A thread of its own receives the packets & runs the timers, while the sockets act on the connection table
+// from the threads of the application, waiting for the stack thread to change it where they have to block.
+// The stack thread stops once the stack & every socket opened on it have been dropped.
+#[derive(Clone)]
+pub struct Stack {
+    inner: Arc<StackInner>,
+    // Only held to keep the stack thread running for as long as there is a handle.
+    _thread: Arc<StackThread>,
+}
+
+struct StackInner {
+    nic: Iface,
+    // The address of our end of the connections we open.
+    address: IPAddress,
+    shared: Mutex<Shared>,
+    // Signalled whenever the connection table has changed.
+    changed: Condvar,
+    // A pipe waking the stack thread up, so it picks up timers the sockets have started.
+    wake_read: RawFd,
+    wake_write: RawFd,
+    // Set once the last handle of the stack has been dropped, telling the stack thread to stop.
+    shutdown: AtomicBool,
+}
+
+// The stack thread, which is shut down & joined once the last handle to the stack is dropped.
+// It only holds the inner stack itself, so the handles keep it running rather than the thread keeping itself.
+struct StackThread {
+    inner: Arc<StackInner>,
+    thread: Option<JoinHandle<()>>,
+}
+
+pub(crate) struct Shared {
+    pub connections: ConnectionTable,
+    pub udp_sockets: UdpSockets,
+    next_ephemeral_port: u16,
+    // Why the stack thread stopped, after which nothing is received or retransmitted and every socket call fails.
+    stopped: Option<String>,
+}
+
+impl Shared {
+    // Fails once the stack thread has stopped, with the reason it stopped.
+    pub(crate) fn check_running(&self) -> io::Result<()> {
+        match &self.stopped {
+            Some(err) => Err(io::Error::other(format!("stack stopped: {}", err))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Stack {
+    // Opens the tun interface with the given name and starts the stack thread on it.
+    // Connections we open use the given address as our end and every connection uses the given congestion control,
+    // unless its socket chooses another.
+    pub fn new(
+        interface_name: &str,
+        address: IPAddress,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> eyre::Result<Stack> {
+        let nic = Iface::new(interface_name, tun_tap::Mode::Tun)
+            .wrap_err("failed to setup tun interface")?;
+        let mtu = get_mtu(&nic).wrap_err("failed to get the mtu of the tun interface")?;
+
+        let mut pipe = [0; 2];
+        if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error()).wrap_err("opening wake pipe");
+        }
+
+        let inner = Arc::new(StackInner {
+            nic,
+            address,
+            shared: Mutex::new(Shared {
+                connections: ConnectionTable::new(mtu, congestion_control),
+                udp_sockets: UdpSockets::default(),
+                next_ephemeral_port: *EPHEMERAL_PORTS.start(),
+                stopped: None,
+            }),
+            changed: Condvar::new(),
+            wake_read: pipe[0],
+            wake_write: pipe[1],
+            shutdown: AtomicBool::new(false),
+        });
+
+        let thread_inner = inner.clone();
+        let thread = thread::Builder::new()
+            .name("rtcp".to_string())
+            .spawn(move || {
+                if let Err(err) = thread_inner.run() {
+                    debug!("stack stopped: {:#}", err);
+                    thread_inner.stop(err);
+                }
+            })
+            .wrap_err("failed to start stack thread")?;
+
+        Ok(Stack {
+            inner: inner.clone(),
+            _thread: Arc::new(StackThread {
+                inner,
+                thread: Some(thread),
+            }),
+        })
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.inner.lock()
+    }
+
+    // Releases the lock until the connection table has changed.
+    pub(crate) fn wait<'a>(&'a self, shared: MutexGuard<'a, Shared>) -> MutexGuard<'a, Shared> {
+        self.inner
+            .changed
+            .wait(shared)
+            .expect("stack poisoned by a panicking thread")
+    }
+
+    // Sends the segments a socket call resulted in on the connection of the quad,
+    // and wakes the stack thread up so it runs any timer the call started.
+    pub(crate) fn send(&self, quad: &TCPQuad, segments: Vec<TCP>) -> io::Result<()> {
+        for segment in segments {
+            send_segment(&self.inner.nic, quad, segment).map_err(to_io_error)?;
+        }
+
+        self.inner.wake();
+        self.inner.changed.notify_all();
+        Ok(())
+    }
+
+    // Sends the data as a datagram from the port of our address to the port of the remote address.
+    #[cfg(feature = "async")]
+    pub(crate) fn send_datagram(
+        &self,
+        local_port: u16,
+        remote_ip: IPAddress,
+        remote_port: u16,
+        data: Vec<u8>,
+    ) -> io::Result<()> {
+        self.lock().check_running()?;
+        let mut udp = UDP {
+            src_port: local_port,
+            dst_port: remote_port,
+            length: 0,
+            checksum: 0,
+            data,
+        };
+        udp.length = udp.len().map_err(to_io_error)?;
+
+        let ip_layer = IPLayerProtocol::generate(
+            self.inner.address.clone(),
+            remote_ip,
+            TransportLayer::UDP(udp),
+        )
+        .wrap_err("failed generating ip layer")
+        .map_err(to_io_error)?;
+
+        send_response(&self.inner.nic, TunLayer::generate_response(ip_layer)).map_err(to_io_error)
+    }
+
+    // The quad of a new connection from our address to the remote end, with a local port no connection uses yet.
+    pub(crate) fn ephemeral_quad(
+        &self,
+        shared: &mut Shared,
+        remote_ip: IPAddress,
+        remote_port: u16,
+    ) -> io::Result<TCPQuad> {
+        for _ in EPHEMERAL_PORTS {
+            let port = shared.next_ephemeral_port;
+            shared.next_ephemeral_port = if port == *EPHEMERAL_PORTS.end() {
+                *EPHEMERAL_PORTS.start()
+            } else {
+                port + 1
+            };
+
+            let quad = TCPQuad {
+                src_ip: remote_ip.clone(),
+                dst_ip: self.inner.address.clone(),
+                src_port: remote_port,
+                dst_port: port,
+            };
+            if shared.connections.get(&quad).is_none() {
+                return Ok(quad);
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "no ephemeral port left",
+        ))
+    }
+
+    // Sets the congestion control of the connections opened or accepted from now on,
+    // unless their socket chooses another.
+    pub fn set_congestion_control(&self, congestion_control: CongestionControlAlgorithm) {
+        self.lock()
+            .connections
+            .set_default_congestion_control(congestion_control);
+    }
+
+    // Enables or disables answering the SYNs that do not fit in the backlog of a listener with SYN cookies,
+    // without them those SYNs are dropped.
+    pub fn set_syn_cookies(&self, enabled: bool) {
+        self.lock().connections.set_syn_cookies(enabled);
+    }
+}
+
+impl StackInner {
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared
+            .lock()
+            .expect("stack poisoned by a panicking thread")
+    }
+
+    // Wakes the stack thread up through the pipe.
+    fn wake(&self) {
+        let byte = 1u8;
+        unsafe { libc::write(self.wake_write, &byte as *const u8 as *const _, 1) };
+    }
+
+    // Receives packets & runs the timers until the tun interface fails or the stack is shut down.
+    fn run(&self) -> eyre::Result<()> {
+        let nic = &self.nic;
+        // Room for the largest packet together with the 4 byte packet information header of the tun interface.
+        let mut buf = vec![0u8; self.lock().connections.maximum_transmission_unit() as usize + 4];
+        while !self.shutdown.load(Ordering::SeqCst) {
+            // Wait for the next packet, but no longer than until the next timer expires.
+            let timeout = self
+                .lock()
+                .connections
+                .next_timer_expiry()
+                .map(|expiry| expiry.saturating_duration_since(Instant::now()));
+
+            if wait_for_packet(nic, self.wake_read, timeout)
+                .wrap_err("failed waiting for packet")?
+            {
+                // If n_bytes == buf.len() we need to append more data before sending it onwards.
+                let n_bytes = nic
+                    .recv(&mut buf[..])
+                    .wrap_err("failed to receive packet")?;
+
+                // A packet we can't handle is dropped, the other connections carry on.
+                if let Err(err) = self.handle_packet(&buf[..n_bytes]) {
+                    debug!("dropped packet: {:#}", err);
+                }
+            }
+
+            let expired = self.lock().connections.on_timers_expired(Instant::now());
+            for (quad, segment) in expired {
+                if let Err(err) = send_segment(nic, &quad, segment) {
+                    debug!("failed to send segment: {:#}", err);
+                }
+            }
+
+            self.changed.notify_all();
+        }
+
+        Ok(())
+    }
+
+    // Parses a packet received on the tun interface, handles it and sends the responses.
+    fn handle_packet(&self, packet: &[u8]) -> eyre::Result<()> {
+        let tun_layer = TunLayer::parse(&mut &packet[..]).wrap_err("failed to parse tun layer")?;
+
+        let responses =
+            handle_tun_layer(tun_layer, &mut self.lock()).wrap_err("failed parsing ip layer")?;
+        for resp in responses {
+            send_response(&self.nic, resp).wrap_err("failed to send response")?;
+        }
+
+        Ok(())
+    }
+
+    // Fails every socket once the stack thread has stopped, waking up all of them so none waits forever.
+    fn stop(&self, err: eyre::Report) {
+        let mut shared = self.lock();
+        shared.stopped = Some(format!("{:#}", err));
+        shared.connections.wake_all();
+        shared.udp_sockets.wake_all();
+        drop(shared);
+
+        self.changed.notify_all();
+    }
+}
+
+impl Drop for StackThread {
+    // Nothing can use the stack anymore, stop the stack thread & wait for it to finish.
+    // Connections still closing are abandoned, without the thread nothing retransmits their FINs.
+    fn drop(&mut self) {
+        self.inner.shutdown.store(true, Ordering::SeqCst);
+        self.inner.wake();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for StackInner {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.wake_read);
+            libc::close(self.wake_write);
+        }
+    }
+}
+
+pub(crate) fn to_io_error(err: eyre::Report) -> io::Error {
+    io::Error::other(format!("{:#}", err))
+}
+
+// Reads the MTU of the nic, i.e. the largest packet it sends & receives.
+fn get_mtu(nic: &Iface) -> eyre::Result<u16> {
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    // Leave the last byte of the name as the terminating null byte.
+    for (name, byte) in request.ifr_name[..libc::IFNAMSIZ - 1]
+        .iter_mut()
+        .zip(nic.name().bytes())
+    {
+        *name = byte as libc::c_char;
+    }
+
+    // Any socket will do to query the interface.
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+    if socket < 0 {
+        return Err(std::io::Error::last_os_error()).wrap_err("opening socket");
+    }
+
+    let result = unsafe { libc::ioctl(socket, libc::SIOCGIFMTU, &mut request) };
+    let err = std::io::Error::last_os_error();
+    unsafe { libc::close(socket) };
+    if result < 0 {
+        return Err(err).wrap_err("querying mtu");
+    }
+
+    let mtu = unsafe { request.ifr_ifru.ifru_mtu };
+    u16::try_from(mtu).wrap_err("mtu out of range")
+}
+
+// Blocks until a packet can be read from the nic, the stack is woken up through the pipe
+// or the timeout passes (None waits forever).
+// Returns whether there is a packet to read.
+fn wait_for_packet(nic: &Iface, wake: RawFd, timeout: Option<Duration>) -> eyre::Result<bool> {
+    let timeout_ms: libc::c_int = match timeout {
+        // Round up to not wake up just before the timer expires.
+        Some(timeout) => {
+            libc::c_int::try_from(timeout.as_micros().div_ceil(1000)).unwrap_or(libc::c_int::MAX)
+        }
+        None => -1,
+    };
+
+    let mut poll_fds = [
+        libc::pollfd {
+            fd: nic.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: wake,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    let ready = unsafe { libc::poll(poll_fds.as_mut_ptr(), 2, timeout_ms) };
+    if ready < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(err).wrap_err("polling tun interface");
+    }
+
+    // Empty the pipe, all wake ups so far have been handled.
+    if poll_fds[1].revents & libc::POLLIN != 0 {
+        let mut buf = [0u8; 64];
+        while unsafe { libc::read(wake, buf.as_mut_ptr() as *mut _, buf.len()) } > 0 {}
+    }
+
+    Ok(ready > 0 && poll_fds[0].revents & libc::POLLIN != 0)
+}
+
+fn handle_tun_layer(tun_layer: TunLayer, shared: &mut Shared) -> eyre::Result<Vec<TunLayer>> {
+    let responses: Vec<IPLayerProtocol> = match tun_layer.data {
+        IPLayerProtocol::IPv6(ipv6) => {
+            trace!("{}", ipv6.to_short_string());
+            handle_transport_layer(
+                &ipv6.data,
+                shared,
+                ipv6.source_address.clone().into(),
+                ipv6.destination_address.clone().into(),
+            )
+            .wrap_err("handling ipv6 packaet")?
+            .into_iter()
+            .map(|response| {
+                let response = ipv6
+                    .generate_response(response)
+                    .wrap_err("failed generating an ipv6 response")?;
+                Ok(response.into())
+            })
+            .collect::<eyre::Result<_>>()?
+        }
+        IPLayerProtocol::IPv4(ipv4) => {
+            trace!("{}", ipv4.to_short_string());
+
+            handle_transport_layer(
+                &ipv4.data,
+                shared,
+                ipv4.source_address.clone().into(),
+                ipv4.destination_address.clone().into(),
+            )
+            .wrap_err("handling ipv4 packet")?
+            .into_iter()
+            .map(|response| {
+                let response = ipv4
+                    .generate_response(response)
+                    .wrap_err("failed generating an ipv4 response")?;
+
+                Ok(response.into())
+            })
+            .collect::<eyre::Result<_>>()?
+        }
+        IPLayerProtocol::Other(_) => {
+            trace!("unsupported protocol: {}", tun_layer.proto);
+            vec![]
+        }
+    };
+
+    Ok(responses
+        .into_iter()
+        .map(TunLayer::generate_response)
+        .collect())
+}
+
+fn handle_transport_layer(
+    data: &TransportLayer,
+    shared: &mut Shared,
+    source_address: IPAddress,
+    destination_address: IPAddress,
+) -> eyre::Result<Vec<TransportLayer>> {
+    match data {
+        TransportLayer::UDP(udp) => shared.udp_sockets.deliver(source_address, udp),
+        TransportLayer::TCP(tcp) => {
+            let quad = TCPQuad {
+                src_ip: source_address.clone(),
+                dst_ip: destination_address.clone(),
+                src_port: tcp.src_port,
+                dst_port: tcp.dst_port,
+            };
+
+            // Does this warrant a response?
+            return Ok(shared
+                .connections
+                .on_segment_received(quad, tcp)
+                .into_iter()
+                .map(TransportLayer::TCP)
+                .collect());
+        }
+        _ => {}
+    }
+
+    Ok(vec![])
+}
+
+// Sends a segment that is not a direct response to a received packet.
+fn send_segment(nic: &Iface, quad: &TCPQuad, segment: TCP) -> eyre::Result<()> {
+    let ip_layer = IPLayerProtocol::generate(
+        quad.dst_ip.clone(),
+        quad.src_ip.clone(),
+        TransportLayer::TCP(segment),
+    )
+    .wrap_err("failed generating ip layer")?;
+
+    send_response(nic, TunLayer::generate_response(ip_layer))
+}
+
+fn send_response(nic: &Iface, response: TunLayer) -> eyre::Result<()> {
+    let serialized = response
+        .serialize()
+        .wrap_err("failed serializing tun_layer response")?;
+
+    // Ensure that we can parse the serialized response.
+    match TunLayer::parse(&mut serialized.as_slice()) {
+        Ok(resp) => trace!("responding with {}", resp.to_short_string()),
+        Err(err) => eyre::bail!("Failed to parse response, this means we can generate responses we ourselves cannot parse (BAD!), err: {err}"),
+    }
+
+    nic.send(serialized.as_slice())
+        .wrap_err("failed to send response")?;
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use crate::layers::transport_layer::tcp::keepalive::Keepalive;
+use crate::layers::transport_layer::tcp::listener::ListenAddress;
+use crate::stack::tcp_stream::TcpStream;
+use crate::stack::{to_io_error, Shared, Stack};
+
+// The most connections that may be half-open or waiting to be accepted at once, the default of std::net.
+const BACKLOG: usize = 128;
+
+// A socket accepting the connections peers open to an address & port of ours, like std::net::TcpListener.
+pub struct TcpListener {
+    stack: Stack,
+    address: ListenAddress,
+}
+
+impl TcpListener {
+    // Starts accepting connections to the port on the address, any of our addresses if it is None.
+    pub fn bind(stack: &Stack, ip: Option<IPAddress>, port: u16) -> io::Result<TcpListener> {
+        let address = ListenAddress { ip, port };
+        let mut shared = stack.lock();
+        shared.check_running()?;
+        shared
+            .connections
+            .listen(address.clone(), BACKLOG)
+            .map_err(|err| io::Error::new(io::ErrorKind::AddrInUse, format!("{:#}", err)))?;
+
+        Ok(TcpListener {
+            stack: stack.clone(),
+            address,
+        })
+    }
+
+    // Blocks until a peer has established a connection, returning it together with the address & port of the peer.
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let mut shared = self.stack.lock();
+        loop {
+            if let Some(accepted) = self.try_accept(&mut shared)? {
+                return Ok(accepted);
+            }
+            shared = self.stack.wait(shared);
+        }
+    }
+
+    // Takes the oldest established connection off the accept queue, None if there is none yet.
+    pub(crate) fn try_accept(
+        &self,
+        shared: &mut Shared,
+    ) -> io::Result<Option<(TcpStream, SocketAddr)>> {
+        shared.check_running()?;
+        let accepted = shared
+            .connections
+            .accept(&self.address)
+            .map_err(to_io_error)?
+            .map(|quad| {
+                let peer = SocketAddr::new(quad.src_ip.clone().into(), quad.src_port);
+                (TcpStream::new(self.stack.clone(), quad), peer)
+            });
+        Ok(accepted)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    // An iterator over the connections accepted on the listener, like std::net::TcpListener::incoming.
+    pub fn incoming(&self) -> impl Iterator<Item = io::Result<TcpStream>> + '_ {
+        std::iter::repeat_with(move || self.accept().map(|(stream, _)| stream))
+    }
+
+    // The address & port the listener accepts connections on, the unspecified address if it accepts them on any.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        let ip = match &self.address.ip {
+            Some(ip) => ip.clone().into(),
+            None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        };
+        Ok(SocketAddr::new(ip, self.address.port))
+    }
+
+    // Enables keepalive with the given settings for the connections accepted from now on, or disables it with None.
+    pub fn set_keepalive(&self, keepalive: Option<Keepalive>) -> io::Result<()> {
+        self.stack
+            .lock()
+            .connections
+            .set_listener_keepalive(&self.address, keepalive)
+            .map_err(to_io_error)
+    }
+
+    // Sets the congestion control of the connections accepted from now on.
+    pub fn set_congestion_control(
+        &self,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> io::Result<()> {
+        self.stack
+            .lock()
+            .connections
+            .set_listener_congestion_control(&self.address, congestion_control)
+            .map_err(to_io_error)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn address(&self) -> &ListenAddress {
+        &self.address
+    }
+}
+
+impl Drop for TcpListener {
+    // Stops accepting connections, resetting those that have not been accepted yet.
+    fn drop(&mut self) {
+        let resets = self.stack.lock().connections.unlisten(&self.address);
+        for (quad, reset) in resets.into_iter().flatten() {
+            let _ = self.stack.send(&quad, vec![reset]);
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use crate::layers::transport_layer::tcp::connection_table::ConnectionEnd;
+use crate::layers::transport_layer::tcp::keepalive::Keepalive;
+use crate::layers::transport_layer::tcp::states::tcp_state::TcpState;
+use crate::layers::transport_layer::tcp::tcb::TCB;
+use crate::layers::transport_layer::tcp::tcp_ip_port_quad::TCPQuad;
+use crate::stack::{to_io_error, Shared, Stack};
+
+// The most written data a connection buffers until the peer acknowledges it, writing more blocks until it does.
+const SEND_BUFFER_CAPACITY: usize = 256 * 1024;
+
+// A TCP connection between our end and a remote end, like std::net::TcpStream.
+pub struct TcpStream {
+    stack: Stack,
+    quad: TCPQuad,
+    // Whether the application has shut down reading, after which reads return nothing.
+    read_shutdown: AtomicBool,
+    // Whether we have seen the peer close its side, so the connection ending afterwards is no error.
+    peer_closed: AtomicBool,
+}
+
+impl TcpStream {
+    // Opens a connection to the port of the remote address, blocking until it is established.
+    pub fn connect(stack: &Stack, remote_ip: IPAddress, remote_port: u16) -> io::Result<TcpStream> {
+        let mut shared = stack.lock();
+        let stream = TcpStream::open(stack, &mut shared, remote_ip, remote_port)?;
+
+        loop {
+            if let Some(result) = stream.try_connected(&shared) {
+                // Dropping a stream that failed to connect takes the lock.
+                drop(shared);
+                return result.map(|_| stream);
+            }
+            shared = stack.wait(shared);
+        }
+    }
+
+    // Sends the SYN opening a connection to the port of the remote address, without waiting for the answer.
+    pub(crate) fn open(
+        stack: &Stack,
+        shared: &mut Shared,
+        remote_ip: IPAddress,
+        remote_port: u16,
+    ) -> io::Result<TcpStream> {
+        shared.check_running()?;
+        let quad = stack.ephemeral_quad(shared, remote_ip, remote_port)?;
+        let segments = shared
+            .connections
+            .connect(
+                quad.clone(),
+                shared.connections.default_congestion_control(),
+            )
+            .map_err(to_io_error)?;
+        stack.send(&quad, segments)?;
+
+        Ok(TcpStream::new(stack.clone(), quad))
+    }
+
+    // Whether the connection we opened has been established, None while the handshake is still going on.
+    pub(crate) fn try_connected(&self, shared: &Shared) -> Option<io::Result<()>> {
+        if let Err(err) = shared.check_running() {
+            return Some(Err(err));
+        }
+
+        match shared.connections.get(&self.quad).map(|tcb| &tcb.state) {
+            Some(TcpState::SynSent) | Some(TcpState::SynReceived) => None,
+            Some(_) => Some(Ok(())),
+            None => Some(Err(match shared.connections.connection_end(&self.quad) {
+                Some(ConnectionEnd::TimedOut) => {
+                    io::Error::new(io::ErrorKind::TimedOut, "connection timed out")
+                }
+                _ => io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused"),
+            })),
+        }
+    }
+
+    // Takes data the peer has sent into the buffer, returning how much, or 0 once the peer has closed its side.
+    // None if there is no data yet.
+    pub(crate) fn try_read(
+        &self,
+        shared: &mut Shared,
+        buf: &mut [u8],
+    ) -> Option<io::Result<usize>> {
+        if let Err(err) = shared.check_running() {
+            return Some(Err(err));
+        }
+        if buf.is_empty() || self.read_shutdown.load(Ordering::SeqCst) {
+            return Some(Ok(0));
+        }
+
+        let closed = shared.connections.connection_end(&self.quad) == Some(ConnectionEnd::Closed);
+        match shared.connections.get(&self.quad) {
+            Some(tcb) => self.observe(tcb),
+            // What the closed connection left behind is read before reading returns nothing.
+            None if closed => {}
+            None if self.peer_closed.load(Ordering::SeqCst) => return Some(Ok(0)),
+            None => return Some(Err(self.ended_error(shared))),
+        }
+
+        if shared.connections.unread_length(&self.quad) > 0 {
+            let result = shared
+                .connections
+                .read(self.quad.clone(), buf.len())
+                .map_err(to_io_error)
+                .and_then(|(data, segments)| {
+                    buf[..data.len()].copy_from_slice(&data);
+                    self.stack.send(&self.quad, segments)?;
+                    Ok(data.len())
+                });
+            return Some(result);
+        }
+
+        if closed || self.peer_closed.load(Ordering::SeqCst) {
+            return Some(Ok(0));
+        }
+
+        None
+    }
+
+    // Writes as much of the data as the connection has room to buffer, returning how much.
+    // None if there is no room yet.
+    pub(crate) fn try_write(&self, shared: &mut Shared, buf: &[u8]) -> Option<io::Result<usize>> {
+        if let Err(err) = shared.check_running() {
+            return Some(Err(err));
+        }
+        if buf.is_empty() {
+            return Some(Ok(0));
+        }
+
+        let tcb = match shared.connections.get(&self.quad) {
+            Some(tcb) => tcb,
+            None => return Some(Err(self.ended_error(shared))),
+        };
+
+        let writable = matches!(
+            tcb.state,
+            TcpState::SynSent | TcpState::SynReceived | TcpState::Established | TcpState::CloseWait
+        );
+        if !writable {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "connection shut down for writing",
+            )));
+        }
+
+        let room = SEND_BUFFER_CAPACITY.saturating_sub(tcb.send_buffer.len());
+        if room == 0 {
+            return None;
+        }
+
+        let length = room.min(buf.len());
+        let result = shared
+            .connections
+            .write(self.quad.clone(), &buf[..length])
+            .map_err(to_io_error)
+            .and_then(|segments| self.stack.send(&self.quad, segments))
+            .map(|_| length);
+        Some(result)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn quad(&self) -> &TCPQuad {
+        &self.quad
+    }
+
+    pub(crate) fn new(stack: Stack, quad: TCPQuad) -> TcpStream {
+        TcpStream {
+            stack,
+            quad,
+            read_shutdown: AtomicBool::new(false),
+            peer_closed: AtomicBool::new(false),
+        }
+    }
+
+    // The address & port of the remote end of the connection.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::new(
+            self.quad.src_ip.clone().into(),
+            self.quad.src_port,
+        ))
+    }
+
+    // The address & port of our end of the connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::new(
+            self.quad.dst_ip.clone().into(),
+            self.quad.dst_port,
+        ))
+    }
+
+    // Shuts down reading, writing or both halves of the connection.
+    // Shutting down writing sends our FIN once everything written before has been sent.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        if how != Shutdown::Write {
+            self.read_shutdown.store(true, Ordering::SeqCst);
+        }
+        if how == Shutdown::Read {
+            return Ok(());
+        }
+
+        let mut shared = self.stack.lock();
+        match shared.connections.get(&self.quad) {
+            Some(tcb) => self.observe(tcb),
+            None => return Err(self.ended_error(&shared)),
+        }
+
+        let segments = shared
+            .connections
+            .close(self.quad.clone())
+            .map_err(to_io_error)?;
+        self.stack.send(&self.quad, segments)
+    }
+
+    // Disables or re-enables Nagle's algorithm for the connection (TCP_NODELAY).
+    pub fn set_nodelay(&self, no_delay: bool) -> io::Result<()> {
+        let segments = self
+            .stack
+            .lock()
+            .connections
+            .set_no_delay(self.quad.clone(), no_delay)
+            .map_err(to_io_error)?;
+        self.stack.send(&self.quad, segments)
+    }
+
+    // Enables keepalive for the connection with the given settings (SO_KEEPALIVE), or disables it with None.
+    pub fn set_keepalive(&self, keepalive: Option<Keepalive>) -> io::Result<()> {
+        self.stack
+            .lock()
+            .connections
+            .set_keepalive(self.quad.clone(), keepalive)
+            .map_err(to_io_error)?;
+        // Wake the stack thread up so it runs the keepalive timer.
+        self.stack.send(&self.quad, vec![])
+    }
+
+    // Switches the connection to the given congestion control (TCP_CONGESTION).
+    pub fn set_congestion_control(
+        &self,
+        congestion_control: CongestionControlAlgorithm,
+    ) -> io::Result<()> {
+        let segments = self
+            .stack
+            .lock()
+            .connections
+            .set_congestion_control(self.quad.clone(), congestion_control)
+            .map_err(to_io_error)?;
+        self.stack.send(&self.quad, segments)
+    }
+
+    // Sends the data as urgent data (MSG_OOB), of which the peer reads the last byte out of band.
+    pub fn send_urgent(&self, data: &[u8]) -> io::Result<()> {
+        let mut shared = self.stack.lock();
+        shared.check_running()?;
+        if shared.connections.get(&self.quad).is_none() {
+            return Err(self.ended_error(&shared));
+        }
+
+        let segments = shared
+            .connections
+            .write_urgent(self.quad.clone(), data)
+            .map_err(to_io_error)?;
+        self.stack.send(&self.quad, segments)
+    }
+
+    // Takes the byte of urgent data the peer has sent out of band (MSG_OOB), None if there is none.
+    pub fn recv_urgent(&self) -> io::Result<Option<u8>> {
+        let mut shared = self.stack.lock();
+        shared.check_running()?;
+        if shared.connections.get(&self.quad).is_none() {
+            return Err(self.ended_error(&shared));
+        }
+
+        shared
+            .connections
+            .read_urgent(self.quad.clone())
+            .map_err(to_io_error)
+    }
+
+    // Whether the next byte to read follows the urgent data the peer has sent (SIOCATMARK),
+    // reads stop at the mark so the data sent before the urgent data can be told apart.
+    pub fn at_urgent_mark(&self) -> io::Result<bool> {
+        let shared = self.stack.lock();
+        shared.check_running()?;
+        match shared.connections.get(&self.quad) {
+            Some(tcb) => Ok(tcb.is_at_urgent_mark()),
+            None => Err(self.ended_error(&shared)),
+        }
+    }
+
+    // Remembers whether the peer has closed its side of the connection.
+    fn observe(&self, tcb: &TCB) {
+        let peer_closed = matches!(
+            tcb.state,
+            TcpState::CloseWait | TcpState::CLosing | TcpState::LastAck | TcpState::TimeWait
+        );
+        if peer_closed {
+            self.peer_closed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // The error for using a connection that has ended.
+    fn ended_error(&self, shared: &Shared) -> io::Error {
+        match shared.connections.connection_end(&self.quad) {
+            Some(ConnectionEnd::TimedOut) => {
+                io::Error::new(io::ErrorKind::TimedOut, "connection timed out")
+            }
+            Some(ConnectionEnd::Closed) => {
+                io::Error::new(io::ErrorKind::BrokenPipe, "connection closed")
+            }
+            _ => io::Error::new(io::ErrorKind::ConnectionReset, "connection reset"),
+        }
+    }
+}
+
+impl Read for TcpStream {
+    // Blocks until the peer has sent data, returning 0 once it has closed its side of the connection.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut shared = self.stack.lock();
+        loop {
+            if let Some(result) = self.try_read(&mut shared, buf) {
+                return result;
+            }
+            shared = self.stack.wait(shared);
+        }
+    }
+}
+
+impl Write for TcpStream {
+    // Blocks until the connection has room to buffer some of the data, returning how much of it was written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut shared = self.stack.lock();
+        loop {
+            if let Some(result) = self.try_write(&mut shared, buf) {
+                return result;
+            }
+            shared = self.stack.wait(shared);
+        }
+    }
+
+    // The data is sent as soon as the windows allow, there is nothing to flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for TcpStream {
+    // Closes our side of the connection unless it has been shut down for writing already.
+    fn drop(&mut self) {
+        let mut shared = self.stack.lock();
+        shared.connections.forget(&self.quad);
+        let open = shared.connections.get(&self.quad).is_some_and(|tcb| {
+            matches!(
+                tcb.state,
+                TcpState::SynSent
+                    | TcpState::SynReceived
+                    | TcpState::Established
+                    | TcpState::CloseWait
+            )
+        });
+        if !open {
+            return;
+        }
+
+        if let Ok(segments) = shared.connections.close(self.quad.clone()) {
+            let _ = self.stack.send(&self.quad, segments);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{HashMap, VecDeque};
+use std::task::Waker;
+
+use eyre::ContextCompat;
+
+use crate::layers::ip_layer::IPAddress;
+use crate::layers::transport_layer::udp::udp::UDP;
+
+// The most datagrams a bound port queues until a socket receives them, later ones are dropped.
+const QUEUE_CAPACITY: usize = 256;
+
+// A datagram received on a bound port, together with the address & port it came from.
+pub(crate) struct Datagram {
+    pub source: (IPAddress, u16),
+    pub data: Vec<u8>,
+}
+
+// The UDP ports sockets are bound to, with the datagrams received on them.
+#[derive(Default)]
+pub(crate) struct UdpSockets {
+    ports: HashMap<u16, BoundPort>,
+}
+
+#[derive(Default)]
+struct BoundPort {
+    queue: VecDeque<Datagram>,
+    // The task waiting for a datagram to receive, woken once one arrives.
+    waker: Option<Waker>,
+}
+
+impl UdpSockets {
+    // Starts queueing the datagrams received on the port.
+    pub fn bind(&mut self, port: u16) -> eyre::Result<()> {
+        if self.ports.contains_key(&port) {
+            eyre::bail!("port {} is already bound", port);
+        }
+        self.ports.insert(port, BoundPort::default());
+        Ok(())
+    }
+
+    // Stops receiving on the port, dropping the datagrams nobody has received.
+    pub fn unbind(&mut self, port: u16) {
+        if let Some(BoundPort {
+            waker: Some(waker), ..
+        }) = self.ports.remove(&port)
+        {
+            waker.wake();
+        }
+    }
+
+    // Wakes up every task waiting for a datagram, e.g. because the stack has stopped.
+    pub fn wake_all(&mut self) {
+        for port in self.ports.values_mut() {
+            if let Some(waker) = port.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    // Queues a datagram received from the address for the socket bound to its destination port.
+    // Datagrams to unbound ports or beyond the capacity of the queue are dropped, as UDP does not promise delivery.
+    pub fn deliver(&mut self, source_address: IPAddress, udp: &UDP) {
+        let Some(port) = self.ports.get_mut(&udp.dst_port) else {
+            return;
+        };
+        if port.queue.len() >= QUEUE_CAPACITY {
+            return;
+        }
+
+        port.queue.push_back(Datagram {
+            source: (source_address, udp.src_port),
+            data: udp.data.clone(),
+        });
+        if let Some(waker) = port.waker.take() {
+            waker.wake();
+        }
+    }
+
+    // Takes the oldest datagram received on the port, None if there is none yet.
+    pub fn receive(&mut self, port: u16) -> eyre::Result<Option<Datagram>> {
+        Ok(self
+            .ports
+            .get_mut(&port)
+            .wrap_err("port is not bound")?
+            .queue
+            .pop_front())
+    }
+
+    // Wakes the task up once a datagram arrives on the port.
+    pub fn register_waker(&mut self, port: u16, waker: Waker) -> eyre::Result<()> {
+        self.ports
+            .get_mut(&port)
+            .wrap_err("port is not bound")?
+            .waker = Some(waker);
+        Ok(())
+    }
+}
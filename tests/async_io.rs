@@ -0,0 +1,134 @@
+// Drives the async sockets on a tokio runtime against the kernel's sockets on the other end of a tun interface.
+// Creating & configuring the interface needs CAP_NET_ADMIN, see run.sh.
+#![cfg(feature = "async")]
+
+use std::future::poll_fn;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::pin::Pin;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use rtcp::layers::ip_layer::ipv4::ipv4_address::IPAddressV4;
+use rtcp::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use rtcp::stack::async_io::tcp_listener::TcpListener;
+use rtcp::stack::async_io::tcp_stream::TcpStream;
+use rtcp::stack::async_io::udp_socket::UdpSocket;
+use rtcp::stack::Stack;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Starts a stack on a tun interface of its own, at 10.77.<subnet>.2 with the kernel at 10.77.<subnet>.1.
+fn start_stack(interface_name: &str, subnet: u8) -> (Stack, Ipv4Addr) {
+    let ours = Ipv4Addr::new(10, 77, subnet, 2);
+    let kernel = Ipv4Addr::new(10, 77, subnet, 1);
+
+    let stack = Stack::new(
+        interface_name,
+        IPAddressV4(u32::from(ours)).into(),
+        CongestionControlAlgorithm::NewReno,
+    )
+    .expect("failed to start stack");
+
+    ip(&["addr", "add", &format!("{}/24", kernel), "dev", interface_name]);
+    ip(&["link", "set", "up", "dev", interface_name]);
+
+    (stack, ours)
+}
+
+fn ip(args: &[&str]) {
+    let status = Command::new("ip")
+        .args(args)
+        .status()
+        .expect("failed to run ip");
+    assert!(status.success(), "ip {:?} failed", args);
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+async fn read(stream: &mut TcpStream, buf: &mut [u8]) -> usize {
+    let mut buf = ReadBuf::new(buf);
+    poll_fn(|cx| Pin::new(&mut *stream).poll_read(cx, &mut buf))
+        .await
+        .unwrap();
+    buf.filled().len()
+}
+
+async fn write_all(stream: &mut TcpStream, mut data: &[u8]) {
+    while !data.is_empty() {
+        let n_bytes = poll_fn(|cx| Pin::new(&mut *stream).poll_write(cx, data))
+            .await
+            .unwrap();
+        data = &data[n_bytes..];
+    }
+}
+
+#[test]
+fn tcp_echo() {
+    let (stack, ours) = start_stack("rtcp_test0", 1);
+    let listener = TcpListener::bind(&stack, None, 7).unwrap();
+
+    let kernel = thread::spawn(move || {
+        let mut stream =
+            std::net::TcpStream::connect_timeout(&SocketAddrV4::new(ours, 7).into(), TIMEOUT)
+                .unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream.write_all(b"hello").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let mut echoed = vec![];
+        stream.read_to_end(&mut echoed).unwrap();
+        echoed
+    });
+
+    block_on(async {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 64];
+        loop {
+            let n_bytes = read(&mut stream, &mut buf).await;
+            if n_bytes == 0 {
+                break;
+            }
+            write_all(&mut stream, &buf[..n_bytes]).await;
+        }
+        poll_fn(|cx| Pin::new(&mut stream).poll_shutdown(cx))
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(kernel.join().unwrap(), b"hello");
+}
+
+#[test]
+fn udp_echo() {
+    let (stack, ours) = start_stack("rtcp_test1", 2);
+    let socket = UdpSocket::bind(&stack, 7).unwrap();
+
+    let kernel = thread::spawn(move || {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0").unwrap();
+        socket.set_read_timeout(Some(TIMEOUT)).unwrap();
+        socket.send_to(b"hello", SocketAddrV4::new(ours, 7)).unwrap();
+
+        let mut buf = [0u8; 64];
+        let (n_bytes, _) = socket.recv_from(&mut buf).unwrap();
+        buf[..n_bytes].to_vec()
+    });
+
+    block_on(async {
+        let mut buf = [0u8; 64];
+        let (n_bytes, (remote_ip, remote_port)) = socket.recv_from(&mut buf).await.unwrap();
+        socket
+            .send_to(&buf[..n_bytes], remote_ip, remote_port)
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(kernel.join().unwrap(), b"hello");
+}
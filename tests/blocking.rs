@@ -0,0 +1,206 @@
+// Drives the blocking sockets against the kernel's sockets on the other end of a tun interface.
+// Creating & configuring the interface needs CAP_NET_ADMIN, see run.sh.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4};
+use std::os::unix::io::AsRawFd;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use rtcp::layers::ip_layer::ipv4::ipv4_address::IPAddressV4;
+use rtcp::layers::transport_layer::tcp::congestion_control::CongestionControlAlgorithm;
+use rtcp::stack::tcp_listener::TcpListener;
+use rtcp::stack::tcp_stream::TcpStream;
+use rtcp::stack::Stack;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Starts a stack on a tun interface of its own, at 10.77.<subnet>.2 with the kernel at 10.77.<subnet>.1.
+fn start_stack(interface_name: &str, subnet: u8) -> (Stack, Ipv4Addr) {
+    let ours = Ipv4Addr::new(10, 77, subnet, 2);
+    let kernel = Ipv4Addr::new(10, 77, subnet, 1);
+
+    let stack = Stack::new(
+        interface_name,
+        IPAddressV4(u32::from(ours)).into(),
+        CongestionControlAlgorithm::NewReno,
+    )
+    .expect("failed to start stack");
+
+    assert!(ip(&["addr", "add", &format!("{}/24", kernel), "dev", interface_name]));
+    assert!(ip(&["link", "set", "up", "dev", interface_name]));
+
+    (stack, ours)
+}
+
+// Runs ip with the arguments, returning whether it succeeded.
+fn ip(args: &[&str]) -> bool {
+    Command::new("ip")
+        .args(args)
+        .output()
+        .expect("failed to run ip")
+        .status
+        .success()
+}
+
+#[test]
+fn stack_stops_once_every_handle_is_dropped() {
+    let (stack, _) = start_stack("rtcp_test2", 3);
+    let listener = TcpListener::bind(&stack, None, 7).unwrap();
+
+    // The listener still uses the stack.
+    drop(stack);
+    thread::sleep(Duration::from_millis(100));
+    assert!(ip(&["link", "show", "dev", "rtcp_test2"]));
+
+    // The tun interface is gone once the stack thread has stopped & let go of it.
+    drop(listener);
+    assert!(!ip(&["link", "show", "dev", "rtcp_test2"]));
+}
+
+#[test]
+fn data_is_read_after_the_connection_has_closed() {
+    let (stack, ours) = start_stack("rtcp_test3", 4);
+    let listener = TcpListener::bind(&stack, None, 7).unwrap();
+
+    let kernel = thread::spawn(move || {
+        let mut stream =
+            std::net::TcpStream::connect_timeout(&SocketAddrV4::new(ours, 7).into(), TIMEOUT)
+                .unwrap();
+        stream.write_all(b"hello").unwrap();
+    });
+
+    let (mut stream, _) = listener.accept().unwrap();
+    kernel.join().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    // Closing our side as well ends the connection before anything has been read.
+    stream.shutdown(Shutdown::Write).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let mut data = vec![];
+    stream.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello");
+    assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+}
+
+#[test]
+fn accepted_connection_echoes_until_closed() {
+    let (stack, ours) = start_stack("rtcp_test4", 5);
+    let listener = TcpListener::bind(&stack, None, 7).unwrap();
+    assert_eq!(
+        listener.local_addr().unwrap(),
+        SocketAddr::from(([0, 0, 0, 0], 7))
+    );
+
+    let kernel = thread::spawn(move || {
+        let mut stream =
+            std::net::TcpStream::connect_timeout(&SocketAddrV4::new(ours, 7).into(), TIMEOUT)
+                .unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let local = stream.local_addr().unwrap();
+        stream.write_all(b"hello").unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+
+        let mut echoed = vec![];
+        stream.read_to_end(&mut echoed).unwrap();
+        (local, echoed)
+    });
+
+    let (mut stream, peer) = listener.accept().unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), peer);
+    assert_eq!(stream.local_addr().unwrap(), SocketAddrV4::new(ours, 7).into());
+
+    let mut buf = [0u8; 64];
+    loop {
+        let n_bytes = stream.read(&mut buf).unwrap();
+        if n_bytes == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n_bytes]).unwrap();
+    }
+    drop(stream);
+
+    let (local, echoed) = kernel.join().unwrap();
+    assert_eq!(local, peer);
+    assert_eq!(echoed, b"hello");
+}
+
+#[test]
+fn connection_is_opened_to_a_listening_peer_or_refused() {
+    let (stack, ours) = start_stack("rtcp_test5", 6);
+    let kernel_ip = Ipv4Addr::new(10, 77, 6, 1);
+    let kernel_listener = std::net::TcpListener::bind(SocketAddrV4::new(kernel_ip, 0)).unwrap();
+    let port = kernel_listener.local_addr().unwrap().port();
+
+    let kernel = thread::spawn(move || {
+        let (mut stream, peer) = kernel_listener.accept().unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream.write_all(b"hello").unwrap();
+
+        let mut received = vec![];
+        stream.read_to_end(&mut received).unwrap();
+        (peer, received)
+    });
+
+    let mut stream = TcpStream::connect(&stack, IPAddressV4(u32::from(kernel_ip)).into(), port).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), SocketAddrV4::new(kernel_ip, port).into());
+    let mut buf = [0u8; 5];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    stream.write_all(b"world").unwrap();
+    let local = stream.local_addr().unwrap();
+    drop(stream);
+
+    let (peer, received) = kernel.join().unwrap();
+    assert_eq!(peer, local);
+    assert_eq!(peer.ip(), ours);
+    assert_eq!(received, b"world");
+
+    // Nothing listens on the port anymore, the kernel resets the SYN.
+    let refused = TcpStream::connect(&stack, IPAddressV4(u32::from(kernel_ip)).into(), port);
+    assert_eq!(refused.err().map(|err| err.kind()), Some(ErrorKind::ConnectionRefused));
+}
+
+#[test]
+fn urgent_byte_is_read_out_of_band() {
+    let (stack, ours) = start_stack("rtcp_test6", 7);
+    let listener = TcpListener::bind(&stack, None, 7).unwrap();
+
+    let kernel = thread::spawn(move || {
+        let mut stream =
+            std::net::TcpStream::connect_timeout(&SocketAddrV4::new(ours, 7).into(), TIMEOUT)
+                .unwrap();
+        stream.set_nodelay(true).unwrap();
+        stream.write_all(b"ab").unwrap();
+        let sent = unsafe {
+            libc::send(
+                stream.as_raw_fd(),
+                b"!".as_ptr() as *const _,
+                1,
+                libc::MSG_OOB,
+            )
+        };
+        assert_eq!(sent, 1);
+        stream.write_all(b"cd").unwrap();
+        stream
+    });
+
+    let (mut stream, _) = listener.accept().unwrap();
+    let kernel_stream = kernel.join().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    // Reading stops at the mark the urgent byte was taken out of the stream at.
+    let mut buf = [0u8; 16];
+    let n_bytes = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n_bytes], b"ab");
+    assert!(stream.at_urgent_mark().unwrap());
+    assert_eq!(stream.recv_urgent().unwrap(), Some(b'!'));
+    assert_eq!(stream.recv_urgent().unwrap(), None);
+
+    let n_bytes = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n_bytes], b"cd");
+    drop(kernel_stream);
+}
+